@@ -0,0 +1,237 @@
+use crate::bsdf;
+use crate::exr::ExrImage;
+use crate::framebuffer::Framebuffer;
+use crate::intersections::{nearest_intersected_object, visibility};
+use crate::lights::LightType;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use nalgebra::Vector3;
+
+/// Auxiliary render pass, shown in the viewer or stored as extra EXR layers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AovPass {
+    Depth,
+    Normal,
+    Albedo,
+    ObjectId,
+    Shadow,
+    Direct,
+    Indirect,
+}
+
+impl AovPass {
+    pub const ALL: [AovPass; 7] = [
+        AovPass::Depth,
+        AovPass::Normal,
+        AovPass::Albedo,
+        AovPass::ObjectId,
+        AovPass::Shadow,
+        AovPass::Direct,
+        AovPass::Indirect,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AovPass::Depth => "depth",
+            AovPass::Normal => "normal",
+            AovPass::Albedo => "albedo",
+            AovPass::ObjectId => "objectId",
+            AovPass::Shadow => "shadow",
+            AovPass::Direct => "direct",
+            AovPass::Indirect => "indirect",
+        }
+    }
+}
+
+/// Auxiliary values of one camera sample, taken at the first hit.
+#[derive(Debug, Clone, Copy)]
+pub struct AovSample {
+    /// Distance from the camera, infinite for the background.
+    pub depth: f32,
+    /// World space shading normal, zero for the background.
+    pub normal: Vector3<f32>,
+    /// Surface color in the 0-1 range.
+    pub albedo: Vector3<f32>,
+    /// Index in `Scene::objects`, -1 for the background.
+    pub object_id: f32,
+    /// Fraction of the positional and spot lights' light blocked before reaching the hit.
+    pub shadow: f32,
+    /// Linear radiance split like the tracers' output, 0-1 range.
+    pub direct: Vector3<f32>,
+    pub indirect: Vector3<f32>,
+}
+
+impl AovSample {
+    /// Geometric passes of a camera ray, the lighting passes are left for the tracer to fill.
+    pub fn primary(scene: &Scene, ray: &Ray) -> Self {
+        let res = match nearest_intersected_object(scene, ray, 0.001, f32::MAX) {
+            Some(res) => res,
+            None => {
+                return AovSample {
+                    depth: f32::INFINITY,
+                    normal: Vector3::zeros(),
+                    albedo: Vector3::zeros(),
+                    object_id: -1.0,
+                    shadow: 0.0,
+                    direct: Vector3::zeros(),
+                    indirect: Vector3::zeros(),
+                }
+            }
+        };
+
+        let p = res.intersection_vector;
+        let (visible, lights) = scene
+            .lights
+            .iter()
+            .filter(|light| matches!(light.light_type(), LightType::Positional | LightType::Spot))
+            .map(|light| {
+                let l = light.center() - p;
+                light.attenuation(p) * visibility(scene, &Ray::new(p, l), 0.001, 1.0)
+            })
+            .fold((0.0, 0.0), |(visible, count), v| (visible + v, count + 1.0));
+
+        AovSample {
+            depth: res.intersection_point * ray.direction().norm(),
+            normal: res.normal,
+            albedo: bsdf::albedo(&res),
+            object_id: res.object_index as f32,
+            shadow: if lights > 0.0 {
+                1.0 - visible / lights
+            } else {
+                0.0
+            },
+            direct: Vector3::zeros(),
+            indirect: Vector3::zeros(),
+        }
+    }
+}
+
+/// All auxiliary passes of a frame, rows from top to bottom like `Framebuffer`.
+#[derive(Debug, Clone)]
+pub struct Aovs {
+    pub width: u32,
+    pub height: u32,
+    pub depth: Vec<f32>,
+    pub normal: Framebuffer,
+    pub albedo: Framebuffer,
+    pub object_id: Vec<f32>,
+    pub shadow: Vec<f32>,
+    pub direct: Framebuffer,
+    pub indirect: Framebuffer,
+}
+
+impl Aovs {
+    /// Averages the samples of each pixel, given pixel by pixel. The object id comes from the
+    /// first sample, averaging ids makes no sense.
+    pub fn from_samples(
+        width: u32,
+        height: u32,
+        samples_per_pixel: usize,
+        samples: &[AovSample],
+    ) -> Self {
+        let mut aovs = Aovs {
+            width,
+            height,
+            depth: Vec::with_capacity((width * height) as usize),
+            normal: Framebuffer::new(width, height),
+            albedo: Framebuffer::new(width, height),
+            object_id: Vec::with_capacity((width * height) as usize),
+            shadow: Vec::with_capacity((width * height) as usize),
+            direct: Framebuffer::new(width, height),
+            indirect: Framebuffer::new(width, height),
+        };
+
+        for (i, pixel) in samples.chunks(samples_per_pixel).enumerate() {
+            let count = pixel.len() as f32;
+            let mean = |value: &dyn Fn(&AovSample) -> Vector3<f32>| {
+                pixel.iter().map(value).sum::<Vector3<f32>>() / count
+            };
+
+            aovs.depth
+                .push(pixel.iter().map(|s| s.depth).sum::<f32>() / count);
+            aovs.object_id.push(pixel[0].object_id);
+            aovs.shadow
+                .push(pixel.iter().map(|s| s.shadow).sum::<f32>() / count);
+
+            let normal = mean(&|s| s.normal);
+            aovs.normal.pixels[i] = normal.try_normalize(1e-6).unwrap_or(normal);
+            aovs.albedo.pixels[i] = mean(&|s| s.albedo);
+            aovs.direct.pixels[i] = mean(&|s| s.direct);
+            aovs.indirect.pixels[i] = mean(&|s| s.indirect);
+        }
+
+        aovs
+    }
+
+    /// Stores every pass as layers of `image`, next to the beauty pass.
+    pub fn add_to_exr(&self, image: &mut ExrImage) -> Result<(), String> {
+        image.add_channel("Z", self.depth.clone())?;
+        image.add_rgb_layer(AovPass::Normal.name(), &self.normal)?;
+        image.add_rgb_layer(AovPass::Albedo.name(), &self.albedo)?;
+        image.add_channel(AovPass::ObjectId.name(), self.object_id.clone())?;
+        image.add_channel(AovPass::Shadow.name(), self.shadow.clone())?;
+        image.add_rgb_layer(AovPass::Direct.name(), &self.direct)?;
+        image.add_rgb_layer(AovPass::Indirect.name(), &self.indirect)
+    }
+
+    /// Displayable version of a pass: depth normalized to the farthest hit, normals mapped to
+    /// colors, object ids given distinct colors.
+    pub fn visualize(&self, pass: AovPass) -> Framebuffer {
+        let gray = |values: &[f32]| Framebuffer {
+            width: self.width,
+            height: self.height,
+            pixels: values.iter().map(|&v| Vector3::repeat(v)).collect(),
+        };
+
+        match pass {
+            AovPass::Depth => {
+                let far = self
+                    .depth
+                    .iter()
+                    .cloned()
+                    .filter(|d| d.is_finite())
+                    .fold(1e-6, f32::max);
+                let normalized: Vec<f32> = self.depth.iter().map(|d| (d / far).min(1.0)).collect();
+                gray(&normalized)
+            }
+            AovPass::Normal => Framebuffer {
+                width: self.width,
+                height: self.height,
+                pixels: self
+                    .normal
+                    .pixels
+                    .iter()
+                    .map(|n| n.map(|c| 0.5 + 0.5 * c))
+                    .collect(),
+            },
+            AovPass::Albedo => self.albedo.clone(),
+            AovPass::ObjectId => Framebuffer {
+                width: self.width,
+                height: self.height,
+                pixels: self.object_id.iter().map(|&id| id_color(id)).collect(),
+            },
+            AovPass::Shadow => gray(&self.shadow),
+            AovPass::Direct => self.direct.clone(),
+            AovPass::Indirect => self.indirect.clone(),
+        }
+    }
+}
+
+/// Arbitrary but stable color per object, black for the background.
+fn id_color(id: f32) -> Vector3<f32> {
+    if id < 0.0 {
+        return Vector3::zeros();
+    }
+
+    // golden ratio steps spread consecutive ids around the hue circle
+    let hue = (id * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    match hue as u32 {
+        0 => Vector3::new(1.0, x, 0.0),
+        1 => Vector3::new(x, 1.0, 0.0),
+        2 => Vector3::new(0.0, 1.0, x),
+        3 => Vector3::new(0.0, x, 1.0),
+        4 => Vector3::new(x, 0.0, 1.0),
+        _ => Vector3::new(1.0, 0.0, x),
+    }
+}
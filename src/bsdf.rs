@@ -0,0 +1,621 @@
+use crate::intersections::{tangent_frame, IntersectionRecord};
+use crate::layers::CLEAR_COAT_IOR;
+use crate::materials::ShadingModel;
+use crate::media::{henyey_greenstein, sample_henyey_greenstein};
+use crate::spectral::Ior;
+use nalgebra::Vector3;
+use rand::Rng;
+use std::f32::consts::PI;
+
+// reflectance of common dielectrics at normal incidence, as in glTF
+const DIELECTRIC_F0: f32 = 0.04;
+// boundary of subsurface materials, close to skin and wax
+pub const SUBSURFACE_IOR: f32 = 1.4;
+// perfectly smooth GGX is a delta distribution, keep a tiny lobe instead
+const MIN_ALPHA: f32 = 1e-3;
+
+/// Direction chosen by importance sampling a surface and its throughput weight f * cos / pdf.
+pub struct BsdfSample {
+    pub direction: Vector3<f32>,
+    pub weight: Vector3<f32>,
+    pub pdf: f32,
+    // sampled from a delta lobe (mirror), `pdf` is meaningless then
+    pub specular: bool,
+}
+
+/// Surface color of the hit in the 0-1 range.
+pub fn albedo(res: &IntersectionRecord) -> Vector3<f32> {
+    res.object_color / 255.0
+}
+
+/// Model used at the surface, subsurface materials scatter like a smooth dielectric there.
+fn surface_model(res: &IntersectionRecord) -> ShadingModel {
+    match res.object_model {
+        ShadingModel::Subsurface { .. } => ShadingModel::Dielectric {
+            ior: Ior::Constant(SUBSURFACE_IOR),
+            roughness: 0.0,
+            absorption: Vector3::zeros(),
+        },
+        model => model,
+    }
+}
+
+/// Shading normal flipped to the side of `wo` (unit vector pointing away from the surface).
+pub fn facing_normal(res: &IntersectionRecord, wo: Vector3<f32>) -> Vector3<f32> {
+    if res.normal.dot(&wo) < 0.0 {
+        -res.normal
+    } else {
+        res.normal
+    }
+}
+
+/// Cosine between the light direction and the surface, volumes scatter without one.
+pub fn cos_factor(res: &IntersectionRecord, wo: Vector3<f32>, wi: Vector3<f32>) -> f32 {
+    match res.object_model {
+        ShadingModel::Volume { .. } => 1.0,
+        _ => facing_normal(res, wo).dot(&wi),
+    }
+}
+
+pub fn reflect(d: Vector3<f32>, n: Vector3<f32>) -> Vector3<f32> {
+    d - 2.0 * d.dot(&n) * n
+}
+
+/// Value of the non-delta part of the BSDF for light arriving from `wi` and leaving along `wo`.
+pub fn eval(res: &IntersectionRecord, wo: Vector3<f32>, wi: Vector3<f32>) -> Vector3<f32> {
+    if res.object_coating.is_empty() {
+        return eval_base(res, wo, wi);
+    }
+
+    let n = facing_normal(res, wo);
+    let transmitted = coating_transmittance(res, n.dot(&wo))
+        .component_mul(&coating_transmittance(res, n.dot(&wi)));
+
+    eval_base(res, wo, wi).component_mul(&transmitted) + eval_coat(res, n, wo, wi)
+}
+
+/// Picks the next path direction proportionally to the BSDF (times cosine where possible).
+pub fn sample(
+    res: &IntersectionRecord,
+    wo: Vector3<f32>,
+    rng: &mut impl Rng,
+) -> Option<BsdfSample> {
+    if res.object_coating.is_empty() {
+        return sample_base(res, wo, rng);
+    }
+
+    let n = facing_normal(res, wo);
+    let coat_probability = coat_probability(res, n, wo);
+
+    if rng.gen::<f32>() >= coat_probability {
+        // through the coating to the base, and back out
+        let sample = sample_base(res, wo, rng)?;
+        let transmitted = coating_transmittance(res, n.dot(&wo))
+            .component_mul(&coating_transmittance(res, n.dot(&sample.direction)));
+
+        return Some(BsdfSample {
+            weight: sample.weight.component_mul(&transmitted) / (1.0 - coat_probability),
+            pdf: if sample.specular {
+                sample.pdf
+            } else {
+                pdf(res, wo, sample.direction)
+            },
+            ..sample
+        });
+    }
+
+    let alpha = res.object_coating.roughness().powi(2);
+    if alpha < MIN_ALPHA {
+        return Some(BsdfSample {
+            direction: reflect(-wo, n),
+            weight: coating_fresnel(res, n.dot(&wo)) / coat_probability,
+            pdf: 1.0,
+            specular: true,
+        });
+    }
+
+    let wi = reflect(-wo, sample_ggx_half_vector(n, alpha, rng));
+    let n_dot_l = n.dot(&wi);
+    if n_dot_l <= 0.0 {
+        return None;
+    }
+
+    Some(BsdfSample {
+        direction: wi,
+        weight: eval_coat(res, n, wo, wi) * n_dot_l / (coat_probability * pdf_coat(res, n, wo, wi)),
+        pdf: pdf(res, wo, wi),
+        specular: false,
+    })
+}
+
+/// Solid angle density with which `sample` picks `wi`, ignoring delta lobes.
+pub fn pdf(res: &IntersectionRecord, wo: Vector3<f32>, wi: Vector3<f32>) -> f32 {
+    if res.object_coating.is_empty() {
+        return pdf_base(res, wo, wi);
+    }
+
+    let n = facing_normal(res, wo);
+    let coat_probability = coat_probability(res, n, wo);
+    coat_probability * pdf_coat(res, n, wo, wi) + (1.0 - coat_probability) * pdf_base(res, wo, wi)
+}
+
+/// Fraction of light reflected by a mirror-like bounce, used by the Whitted tracer.
+pub fn mirror_reflectance(res: &IntersectionRecord, wo: Vector3<f32>) -> Vector3<f32> {
+    let base = mirror_reflectance_base(res, wo);
+    if res.object_coating.is_empty() {
+        return base;
+    }
+
+    let n_dot_v = facing_normal(res, wo).dot(&wo);
+    coating_reflectance(res, wo) + base.component_mul(&coating_transmittance(res, n_dot_v))
+}
+
+/// Mirror reflection of the coating alone, zero without one. The Whitted tracer adds it on top
+/// of shading models with their own reflections.
+pub fn coating_reflectance(res: &IntersectionRecord, wo: Vector3<f32>) -> Vector3<f32> {
+    if res.object_coating.is_empty() {
+        return Vector3::zeros();
+    }
+
+    let n_dot_v = facing_normal(res, wo).dot(&wo);
+    // a rough coat blurs its reflection away, like rough metals
+    coating_fresnel(res, n_dot_v) * (1.0 - res.object_coating.roughness()).powi(2)
+}
+
+/// Index of refraction under the coating, where a thin film without clear coat sits.
+fn coating_base_ior(res: &IntersectionRecord) -> f32 {
+    match res.object_model {
+        ShadingModel::Dielectric { ior, .. } => ior.nominal(),
+        _ => CLEAR_COAT_IOR,
+    }
+}
+
+fn coating_fresnel(res: &IntersectionRecord, cos_theta: f32) -> Vector3<f32> {
+    res.object_coating
+        .fresnel(cos_theta.abs(), coating_base_ior(res))
+}
+
+/// Light getting through the coating in one direction, all of it without a coating.
+pub fn coating_transmittance(res: &IntersectionRecord, cos_theta: f32) -> Vector3<f32> {
+    if res.object_coating.is_empty() {
+        return Vector3::new(1.0, 1.0, 1.0);
+    }
+
+    Vector3::new(1.0, 1.0, 1.0) - coating_fresnel(res, cos_theta)
+}
+
+fn coat_probability(res: &IntersectionRecord, n: Vector3<f32>, wo: Vector3<f32>) -> f32 {
+    luminance(coating_fresnel(res, n.dot(&wo))).clamp(0.05, 0.95)
+}
+
+/// Glossy GGX reflection of a rough coat, zero for a smooth (mirror) one.
+fn eval_coat(
+    res: &IntersectionRecord,
+    n: Vector3<f32>,
+    wo: Vector3<f32>,
+    wi: Vector3<f32>,
+) -> Vector3<f32> {
+    let alpha = res.object_coating.roughness().powi(2);
+    if alpha < MIN_ALPHA || n.dot(&wi) <= 0.0 {
+        return Vector3::zeros();
+    }
+
+    let h = (wo + wi).normalize();
+    let n_dot_v = n.dot(&wo).max(1e-4);
+    let n_dot_l = n.dot(&wi).max(1e-4);
+    let d = ggx_distribution(n.dot(&h), alpha);
+    let g = smith_g1(n_dot_v, alpha) * smith_g1(n_dot_l, alpha);
+
+    coating_fresnel(res, wo.dot(&h)) * (d * g / (4.0 * n_dot_v * n_dot_l))
+}
+
+fn pdf_coat(res: &IntersectionRecord, n: Vector3<f32>, wo: Vector3<f32>, wi: Vector3<f32>) -> f32 {
+    let alpha = res.object_coating.roughness().powi(2);
+    if alpha < MIN_ALPHA || n.dot(&wi) <= 0.0 {
+        return 0.0;
+    }
+
+    let h = (wo + wi).normalize();
+    ggx_distribution(n.dot(&h), alpha) * n.dot(&h) / (4.0 * wo.dot(&h).abs())
+}
+
+/// Base model part of `eval`, without coating.
+fn eval_base(res: &IntersectionRecord, wo: Vector3<f32>, wi: Vector3<f32>) -> Vector3<f32> {
+    if let ShadingModel::Volume { g } = res.object_model {
+        return albedo(res) * henyey_greenstein(-wo.dot(&wi), g);
+    }
+
+    let n = facing_normal(res, wo);
+    if n.dot(&wi) <= 0.0 {
+        return Vector3::zeros();
+    }
+
+    match surface_model(res) {
+        ShadingModel::Phong => albedo(res) * (1.0 - res.object_reflective) / PI,
+        ShadingModel::MetallicRoughness {
+            metallic,
+            roughness,
+        } => eval_metallic_roughness(albedo(res), metallic, roughness, n, wo, wi),
+        ShadingModel::Dielectric { ior, roughness, .. } => {
+            // only the glossy reflection can be evaluated, transmission is reached by sampling
+            let alpha = roughness * roughness;
+            if alpha < MIN_ALPHA {
+                return Vector3::zeros();
+            }
+
+            let (eta_i, eta_t) = dielectric_etas(res, wo, ior);
+            let h = (wo + wi).normalize();
+            let n_dot_v = n.dot(&wo).max(1e-4);
+            let n_dot_l = n.dot(&wi).max(1e-4);
+
+            let f = fresnel_dielectric(wo.dot(&h), eta_i, eta_t);
+            let d = ggx_distribution(n.dot(&h), alpha);
+            let g = smith_g1(n_dot_v, alpha) * smith_g1(n_dot_l, alpha);
+            Vector3::repeat(f * d * g / (4.0 * n_dot_v * n_dot_l))
+        }
+        ShadingModel::Subsurface { .. } | ShadingModel::Volume { .. } => unreachable!(),
+    }
+}
+
+/// Base model part of `sample`, without coating.
+fn sample_base(
+    res: &IntersectionRecord,
+    wo: Vector3<f32>,
+    rng: &mut impl Rng,
+) -> Option<BsdfSample> {
+    let n = facing_normal(res, wo);
+
+    match surface_model(res) {
+        ShadingModel::Phong => {
+            // mirror with probability `reflective`, otherwise diffuse, both weights are exact
+            if rng.gen::<f32>() < res.object_reflective {
+                return Some(BsdfSample {
+                    direction: reflect(-wo, n),
+                    weight: Vector3::new(1.0, 1.0, 1.0),
+                    pdf: 1.0,
+                    specular: true,
+                });
+            }
+
+            let wi = cosine_sample_hemisphere(n, rng);
+            Some(BsdfSample {
+                direction: wi,
+                weight: albedo(res),
+                pdf: pdf_base(res, wo, wi),
+                specular: false,
+            })
+        }
+        ShadingModel::MetallicRoughness {
+            metallic,
+            roughness,
+        } => {
+            let base_color = albedo(res);
+            let alpha = (roughness * roughness).max(MIN_ALPHA);
+            let specular_probability = specular_probability(base_color, metallic, n.dot(&wo));
+
+            let wi = if rng.gen::<f32>() < specular_probability {
+                let h = sample_ggx_half_vector(n, alpha, rng);
+                reflect(-wo, h)
+            } else {
+                cosine_sample_hemisphere(n, rng)
+            };
+
+            let n_dot_l = n.dot(&wi);
+            if n_dot_l <= 0.0 {
+                return None;
+            }
+
+            let pdf = pdf_base(res, wo, wi);
+            let f = eval_metallic_roughness(base_color, metallic, roughness, n, wo, wi);
+            Some(BsdfSample {
+                direction: wi,
+                weight: f * n_dot_l / pdf,
+                pdf,
+                specular: false,
+            })
+        }
+        ShadingModel::Dielectric { ior, roughness, .. } => {
+            let alpha = roughness * roughness;
+            let smooth = alpha < MIN_ALPHA;
+            let h = if smooth {
+                n
+            } else {
+                sample_ggx_half_vector(n, alpha, rng)
+            };
+
+            let (eta_i, eta_t) = dielectric_etas(res, wo, ior);
+            let fresnel = fresnel_dielectric(wo.dot(&h), eta_i, eta_t);
+
+            // reflect or refract with probability given by Fresnel, which then cancels out
+            let (wi, transmitted) = match refract(-wo, h, eta_i / eta_t) {
+                Some(refracted) if rng.gen::<f32>() >= fresnel => (refracted, true),
+                _ => (reflect(-wo, h), false),
+            };
+
+            let n_dot_l = n.dot(&wi);
+            if transmitted == (n_dot_l > 0.0) {
+                return None;
+            }
+
+            // Walter et al. 2007, weight for half vectors sampled by D(h) * cos(theta_h)
+            let weight = if smooth {
+                1.0
+            } else {
+                wo.dot(&h).abs()
+                    * smith_g1(n.dot(&wo).abs(), alpha)
+                    * smith_g1(n_dot_l.abs(), alpha)
+                    / (n.dot(&wo).abs() * n.dot(&h).abs())
+            };
+
+            Some(BsdfSample {
+                direction: wi,
+                weight: Vector3::repeat(weight),
+                pdf: if transmitted {
+                    1.0
+                } else {
+                    pdf_base(res, wo, wi)
+                },
+                // transmission can't be reached by light sampling, treat it like a mirror bounce
+                specular: smooth || transmitted,
+            })
+        }
+        ShadingModel::Subsurface { .. } => unreachable!(),
+        ShadingModel::Volume { g } => {
+            // the phase function is sampled exactly, only the albedo remains as weight
+            let wi = sample_henyey_greenstein(-wo, g, rng);
+            Some(BsdfSample {
+                direction: wi,
+                weight: albedo(res),
+                pdf: henyey_greenstein(-wo.dot(&wi), g),
+                specular: false,
+            })
+        }
+    }
+}
+
+/// Base model part of `pdf`, without coating.
+fn pdf_base(res: &IntersectionRecord, wo: Vector3<f32>, wi: Vector3<f32>) -> f32 {
+    if let ShadingModel::Volume { g } = res.object_model {
+        return henyey_greenstein(-wo.dot(&wi), g);
+    }
+
+    let n = facing_normal(res, wo);
+    let n_dot_l = n.dot(&wi);
+    if n_dot_l <= 0.0 {
+        return 0.0;
+    }
+
+    match surface_model(res) {
+        ShadingModel::Phong => (1.0 - res.object_reflective) * n_dot_l / PI,
+        ShadingModel::MetallicRoughness {
+            metallic,
+            roughness,
+        } => {
+            let alpha = (roughness * roughness).max(MIN_ALPHA);
+            let specular_probability = specular_probability(albedo(res), metallic, n.dot(&wo));
+
+            let h = (wo + wi).normalize();
+            let specular_pdf =
+                ggx_distribution(n.dot(&h), alpha) * n.dot(&h) / (4.0 * wo.dot(&h).abs());
+            let diffuse_pdf = n_dot_l / PI;
+
+            specular_probability * specular_pdf + (1.0 - specular_probability) * diffuse_pdf
+        }
+        ShadingModel::Dielectric { ior, roughness, .. } => {
+            let alpha = roughness * roughness;
+            if alpha < MIN_ALPHA {
+                return 0.0;
+            }
+
+            let (eta_i, eta_t) = dielectric_etas(res, wo, ior);
+            let h = (wo + wi).normalize();
+            let fresnel = fresnel_dielectric(wo.dot(&h), eta_i, eta_t);
+
+            fresnel * ggx_distribution(n.dot(&h), alpha) * n.dot(&h) / (4.0 * wo.dot(&h).abs())
+        }
+        ShadingModel::Subsurface { .. } | ShadingModel::Volume { .. } => unreachable!(),
+    }
+}
+
+/// Base model part of `mirror_reflectance`, without coating.
+fn mirror_reflectance_base(res: &IntersectionRecord, wo: Vector3<f32>) -> Vector3<f32> {
+    match surface_model(res) {
+        ShadingModel::Phong => Vector3::repeat(res.object_reflective),
+        ShadingModel::MetallicRoughness {
+            metallic,
+            roughness,
+        } => {
+            let n = facing_normal(res, wo);
+            let f0 = Vector3::repeat(DIELECTRIC_F0).lerp(&albedo(res), metallic);
+            // rough surfaces blur the reflection away, fade it out instead of tracing a cone
+            fresnel_schlick(n.dot(&wo), f0) * (1.0 - roughness).powi(2)
+        }
+        ShadingModel::Dielectric { ior, .. } => {
+            let (eta_i, eta_t) = dielectric_etas(res, wo, ior);
+            Vector3::repeat(fresnel_dielectric(
+                facing_normal(res, wo).dot(&wo),
+                eta_i,
+                eta_t,
+            ))
+        }
+        ShadingModel::Subsurface { .. } => unreachable!(),
+        ShadingModel::Volume { .. } => Vector3::zeros(),
+    }
+}
+
+/// Indices of refraction on the side of `wo` and on the other side of a dielectric surface.
+pub fn dielectric_etas(res: &IntersectionRecord, wo: Vector3<f32>, ior: Ior) -> (f32, f32) {
+    let ior = ior.nominal();
+    if res.geometric_normal.dot(&wo) >= 0.0 {
+        (1.0, ior)
+    } else {
+        (ior, 1.0)
+    }
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface, 1.0 on total internal reflection.
+pub fn fresnel_dielectric(cos_theta_i: f32, eta_i: f32, eta_t: f32) -> f32 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin_theta_t = eta_i / eta_t * (1.0 - cos_theta_i * cos_theta_i).max(0.0).sqrt();
+    if sin_theta_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_theta_t = (1.0 - sin_theta_t * sin_theta_t).max(0.0).sqrt();
+    let parallel =
+        (eta_t * cos_theta_i - eta_i * cos_theta_t) / (eta_t * cos_theta_i + eta_i * cos_theta_t);
+    let perpendicular =
+        (eta_i * cos_theta_i - eta_t * cos_theta_t) / (eta_i * cos_theta_i + eta_t * cos_theta_t);
+
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+/// Refracts direction `d` through a surface with normal `n` (on the side `d` comes from),
+/// `eta` being the ratio of incident to transmitted index. `None` on total internal reflection.
+pub fn refract(d: Vector3<f32>, n: Vector3<f32>, eta: f32) -> Option<Vector3<f32>> {
+    let cos_theta_i = -d.dot(&n);
+    let sin2_theta_t = eta * eta * (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some((eta * d + (eta * cos_theta_i - cos_theta_t) * n).normalize())
+}
+
+/// Beer-Lambert transmittance after traveling `distance` through a medium.
+pub fn transmittance(absorption: Vector3<f32>, distance: f32) -> Vector3<f32> {
+    absorption.map(|sigma| f32::exp(-sigma * distance))
+}
+
+fn eval_metallic_roughness(
+    base_color: Vector3<f32>,
+    metallic: f32,
+    roughness: f32,
+    n: Vector3<f32>,
+    wo: Vector3<f32>,
+    wi: Vector3<f32>,
+) -> Vector3<f32> {
+    let alpha = (roughness * roughness).max(MIN_ALPHA);
+    let h = (wo + wi).normalize();
+    let n_dot_v = n.dot(&wo).max(1e-4);
+    let n_dot_l = n.dot(&wi).max(1e-4);
+
+    let f0 = Vector3::repeat(DIELECTRIC_F0).lerp(&base_color, metallic);
+    let f = fresnel_schlick(wo.dot(&h), f0);
+    let d = ggx_distribution(n.dot(&h), alpha);
+    let g = smith_g1(n_dot_v, alpha) * smith_g1(n_dot_l, alpha);
+
+    let specular = f * (d * g / (4.0 * n_dot_v * n_dot_l));
+    let diffuse_color = base_color * (1.0 - metallic);
+    let diffuse = (Vector3::new(1.0, 1.0, 1.0) - f).component_mul(&diffuse_color) / PI;
+
+    specular + diffuse
+}
+
+fn specular_probability(base_color: Vector3<f32>, metallic: f32, n_dot_v: f32) -> f32 {
+    let f0 = Vector3::repeat(DIELECTRIC_F0).lerp(&base_color, metallic);
+    let specular = luminance(fresnel_schlick(n_dot_v, f0));
+    let diffuse = luminance(base_color) * (1.0 - metallic) * (1.0 - specular);
+
+    if specular + diffuse <= 0.0 {
+        return 1.0;
+    }
+    // keep some samples in each lobe so neither is starved
+    (specular / (specular + diffuse)).clamp(0.1, 1.0)
+}
+
+/// Trowbridge-Reitz (GGX) normal distribution.
+pub fn ggx_distribution(n_dot_h: f32, alpha: f32) -> f32 {
+    if n_dot_h <= 0.0 {
+        return 0.0;
+    }
+    let alpha2 = alpha * alpha;
+    let denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * denominator * denominator)
+}
+
+/// Smith masking term for one direction, GGX form.
+pub fn smith_g1(n_dot_v: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    2.0 * n_dot_v / (n_dot_v + f32::sqrt(alpha2 + (1.0 - alpha2) * n_dot_v * n_dot_v))
+}
+
+pub fn fresnel_schlick(cos_theta: f32, f0: Vector3<f32>) -> Vector3<f32> {
+    let weight = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + (Vector3::new(1.0, 1.0, 1.0) - f0) * weight
+}
+
+/// Half vector distributed as D(h) * cos(theta_h) around `n`.
+pub fn sample_ggx_half_vector(n: Vector3<f32>, alpha: f32, rng: &mut impl Rng) -> Vector3<f32> {
+    let u1: f32 = rng.gen();
+    let u2: f32 = rng.gen();
+
+    let cos_theta = f32::sqrt((1.0 - u1) / (1.0 + (alpha * alpha - 1.0) * u1));
+    let sin_theta = f32::sqrt((1.0 - cos_theta * cos_theta).max(0.0));
+    let phi = 2.0 * PI * u2;
+
+    to_world(
+        n,
+        Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta),
+    )
+}
+
+pub fn cosine_sample_hemisphere(n: Vector3<f32>, rng: &mut impl Rng) -> Vector3<f32> {
+    let u1: f32 = rng.gen();
+    let u2: f32 = rng.gen();
+
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+
+    to_world(
+        n,
+        Vector3::new(r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt()),
+    )
+}
+
+/// Maps a direction given in a frame where z is `n` to world space.
+fn to_world(n: Vector3<f32>, local: Vector3<f32>) -> Vector3<f32> {
+    let (tangent, bitangent) = tangent_frame(n, Vector3::new(1.0, 0.0, 0.0));
+    (tangent * local.x + bitangent * local.y + n * local.z).normalize()
+}
+
+pub fn luminance(color: Vector3<f32>) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ggx_distribution_is_normalized() {
+        // integral of D(h) * cos(theta_h) over the hemisphere must be 1
+        let alpha = 0.3;
+        let steps = 20000;
+        let mut integral = 0.0;
+
+        for i in 0..steps {
+            let theta = (i as f32 + 0.5) / steps as f32 * PI / 2.0;
+            let d = ggx_distribution(theta.cos(), alpha);
+            integral += d * theta.cos() * theta.sin() * 2.0 * PI * (PI / 2.0 / steps as f32);
+        }
+
+        assert!((integral - 1.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_fresnel_dielectric() {
+        // glass at normal incidence reflects about 4%
+        assert!((fresnel_dielectric(1.0, 1.0, 1.5) - 0.04).abs() < 1e-3);
+        // grazing angle from inside is past the critical angle
+        assert_eq!(fresnel_dielectric(0.1, 1.5, 1.0), 1.0);
+    }
+
+    #[test]
+    fn test_fresnel_schlick_limits() {
+        let f0 = Vector3::repeat(0.04);
+        assert!((fresnel_schlick(1.0, f0) - f0).norm() < 1e-6);
+        assert!((fresnel_schlick(0.0, f0) - Vector3::repeat(1.0)).norm() < 1e-6);
+    }
+}
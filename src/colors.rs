@@ -1,3 +1,4 @@
+use crate::framebuffer::srgb_to_linear;
 use nalgebra::Vector3;
 use sdl2::pixels::Color;
 
@@ -21,10 +22,12 @@ pub const ORANGE_YELLOW: Color = Color::RGB(238, 185, 2);
 pub const BLACK: Color = Color::RGB(0, 0, 32);
 pub const WHITE: Color = Color::RGB(255, 255, 255);
 
-pub fn get_color(vec: Vector3<f32>) -> Color {
-    Color::RGB(vec[0] as u8, vec[1] as u8, vec[2] as u8)
-}
-
 pub fn get_vector(col: Color) -> Vector3<f32> {
     Vector3::new(col.r as f32, col.g as f32, col.b as f32)
 }
+
+/// Palette colors are sRGB encoded, this decodes them to linear values in the same 0-255 range
+/// the renderer works with.
+pub fn get_linear_vector(col: Color) -> Vector3<f32> {
+    get_vector(col).map(|c| srgb_to_linear(c / 255.0) * 255.0)
+}
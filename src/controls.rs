@@ -0,0 +1,134 @@
+use nalgebra::Vector3;
+use std::f32::consts::FRAC_PI_2;
+
+// radians per pixel of mouse movement
+const ROTATE_SPEED: f32 = 0.005;
+// fraction of the target distance per pixel of mouse movement
+const PAN_SPEED: f32 = 0.002;
+// distance factor per wheel step
+const DOLLY_FACTOR: f32 = 0.9;
+// degrees per wheel step
+const ZOOM_STEP: f32 = 2.5;
+const MIN_FOV: f32 = 5.0;
+const MAX_FOV: f32 = 120.0;
+const MIN_DISTANCE: f32 = 0.05;
+// keeps the view direction away from the poles, where the up vector would flip
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// Viewer camera orbiting around a target point, with the eye given by yaw, pitch and distance
+/// from the target. In fly mode the eye stays put and the target moves instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitCamera {
+    pub target: Vector3<f32>,
+    pub distance: f32,
+    /// Angle around the y axis, 0 puts the eye on the +x side of the target.
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Vertical field of view in degrees.
+    pub fov: f32,
+    pub fly: bool,
+}
+
+impl OrbitCamera {
+    pub fn looking_at(eye: Vector3<f32>, target: Vector3<f32>, fov: f32) -> Self {
+        let offset = eye - target;
+        let distance = offset.norm().max(MIN_DISTANCE);
+
+        OrbitCamera {
+            target,
+            distance,
+            yaw: offset.z.atan2(offset.x),
+            pitch: (offset.y / distance).clamp(-1.0, 1.0).asin(),
+            fov,
+            fly: false,
+        }
+    }
+
+    pub fn eye(&self) -> Vector3<f32> {
+        self.target + self.offset_direction() * self.distance
+    }
+
+    /// Unit vectors pointing into the view, to the right and up on screen.
+    pub fn basis(&self) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+        let forward = -self.offset_direction();
+        let right = forward.cross(&Vector3::y()).normalize();
+        let up = right.cross(&forward);
+        (forward, right, up)
+    }
+
+    /// Mouse drag by `dx`, `dy` pixels: swings the eye around the target, or in fly mode turns
+    /// the view around the eye.
+    pub fn rotate(&mut self, dx: f32, dy: f32) {
+        let eye = self.eye();
+        self.yaw += dx * ROTATE_SPEED;
+        self.pitch = (self.pitch + dy * ROTATE_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+
+        if self.fly {
+            self.target = eye - self.offset_direction() * self.distance;
+        }
+    }
+
+    /// Drags the scene along with the mouse, moving the eye and target together.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let (_, right, up) = self.basis();
+        self.target += (up * dy - right * dx) * self.distance * PAN_SPEED;
+    }
+
+    /// Moves the eye towards the target, `steps` wheel clicks, negative to move away.
+    pub fn dolly(&mut self, steps: f32) {
+        self.distance = (self.distance * DOLLY_FACTOR.powf(steps)).max(MIN_DISTANCE);
+    }
+
+    /// Narrows the field of view by `steps` wheel clicks, negative to widen it.
+    pub fn zoom(&mut self, steps: f32) {
+        self.fov = (self.fov - steps * ZOOM_STEP).clamp(MIN_FOV, MAX_FOV);
+    }
+
+    /// Moves eye and target along the view: forward, to the right and up in world space.
+    pub fn translate(&mut self, forward: f32, right: f32, up: f32) {
+        let (forward_direction, right_direction, _) = self.basis();
+        self.target += forward_direction * forward + right_direction * right + Vector3::y() * up;
+    }
+
+    fn offset_direction(&self) -> Vector3<f32> {
+        Vector3::new(
+            self.pitch.cos() * self.yaw.cos(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.sin(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_looking_at_round_trip() {
+        let eye = Vector3::new(-0.5, 1.0, 2.0);
+        let target = Vector3::new(3.0, 0.0, -1.0);
+        let camera = OrbitCamera::looking_at(eye, target, 55.0);
+
+        assert!((camera.eye() - eye).norm() < 1e-5);
+        let (forward, _, _) = camera.basis();
+        assert!((forward - (target - eye).normalize()).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_orbit_keeps_target_and_fly_keeps_eye() {
+        let mut camera =
+            OrbitCamera::looking_at(Vector3::zeros(), Vector3::new(4.0, 0.0, 0.0), 55.0);
+        let target = camera.target;
+        camera.rotate(120.0, -40.0);
+        assert_eq!(camera.target, target);
+        assert!(((camera.eye() - target).norm() - 4.0).abs() < 1e-5);
+
+        camera.fly = true;
+        let eye = camera.eye();
+        camera.rotate(-300.0, 80.0);
+        assert!((camera.eye() - eye).norm() < 1e-5);
+
+        camera.translate(1.0, 0.0, 0.0);
+        assert!(((camera.eye() - eye).norm() - 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::aov::Aovs;
+use crate::framebuffer::Framebuffer;
+use nalgebra::Vector3;
+use rayon::prelude::*;
+
+// B3 spline, the a-trous wavelet's smoothing kernel
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// How strongly each feature stops the blur, smaller means sharper edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DenoiseSettings {
+    /// Number of a-trous passes, the filter covers 2^iterations pixels.
+    pub iterations: u32,
+    pub sigma_color: f32,
+    pub sigma_normal: f32,
+    pub sigma_albedo: f32,
+    /// Relative depth difference per pixel of distance.
+    pub sigma_depth: f32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            sigma_color: 0.5,
+            sigma_normal: 0.3,
+            sigma_albedo: 0.1,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+/// Edge-avoiding a-trous wavelet filter (Dammertz et al. 2010) guided by the auxiliary passes,
+/// so noise is averaged away within surfaces but not across their edges.
+pub fn denoise(image: &Framebuffer, aovs: &Aovs, settings: &DenoiseSettings) -> Framebuffer {
+    let mut current = image.clone();
+    let mut sigma_color = settings.sigma_color;
+
+    for iteration in 0..settings.iterations {
+        let step = 1 << iteration;
+        current = atrous_pass(&current, aovs, settings, sigma_color, step);
+        // later passes see less noise, so the color may stop them earlier
+        sigma_color *= 0.5;
+    }
+
+    current
+}
+
+fn atrous_pass(
+    image: &Framebuffer,
+    aovs: &Aovs,
+    settings: &DenoiseSettings,
+    sigma_color: f32,
+    step: i32,
+) -> Framebuffer {
+    let (width, height) = (image.width as i32, image.height as i32);
+
+    let pixels = (0..height)
+        .into_par_iter()
+        .flat_map(|y| {
+            (0..width)
+                .map(|x| {
+                    let p = (y * width + x) as usize;
+                    let mut sum = Vector3::zeros();
+                    let mut total_weight = 0.0;
+
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            let qx = x + (i as i32 - 2) * step;
+                            let qy = y + (j as i32 - 2) * step;
+                            if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                                continue;
+                            }
+                            let q = (qy * width + qx) as usize;
+
+                            let weight = kx
+                                * ky
+                                * edge_weight(image, aovs, settings, sigma_color, step, p, q);
+                            sum += image.pixels[q] * weight;
+                            total_weight += weight;
+                        }
+                    }
+
+                    // the center pixel always has a weight, so this never divides by zero
+                    sum / total_weight
+                })
+                .collect::<Vec<Vector3<f32>>>()
+        })
+        .collect();
+
+    Framebuffer {
+        width: image.width,
+        height: image.height,
+        pixels,
+    }
+}
+
+fn edge_weight(
+    image: &Framebuffer,
+    aovs: &Aovs,
+    settings: &DenoiseSettings,
+    sigma_color: f32,
+    step: i32,
+    p: usize,
+    q: usize,
+) -> f32 {
+    if aovs.object_id[p] != aovs.object_id[q] {
+        return 0.0;
+    }
+
+    let gaussian = |a: Vector3<f32>, b: Vector3<f32>, sigma: f32| {
+        (-(a - b).norm_squared() / (sigma * sigma)).exp()
+    };
+
+    let color = gaussian(image.pixels[p], image.pixels[q], sigma_color);
+    let normal = gaussian(
+        aovs.normal.pixels[p],
+        aovs.normal.pixels[q],
+        settings.sigma_normal,
+    );
+    let albedo = gaussian(
+        aovs.albedo.pixels[p],
+        aovs.albedo.pixels[q],
+        settings.sigma_albedo,
+    );
+
+    color * normal * albedo * depth_weight(aovs.depth[p], aovs.depth[q], settings, step)
+}
+
+/// Background pixels have infinite depth: they only mix with each other.
+fn depth_weight(depth_p: f32, depth_q: f32, settings: &DenoiseSettings, step: i32) -> f32 {
+    match (depth_p.is_finite(), depth_q.is_finite()) {
+        (false, false) => 1.0,
+        (true, true) => {
+            let difference = (depth_p - depth_q).abs() / depth_p.max(1e-4);
+            (-difference / (settings.sigma_depth * step as f32)).exp()
+        }
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_noise_is_removed_but_edges_are_kept() {
+        let (width, height) = (32, 16);
+        let mut image = Framebuffer::new(width, height);
+        let mut aovs = Aovs::from_samples(width, height, 1, &[]);
+        // seeded, so the error bound is checked on the same image every run
+        let mut rng = StdRng::seed_from_u64(7);
+
+        // two objects side by side, dark on the left and bright on the right
+        for y in 0..height {
+            for x in 0..width {
+                let left = x < width / 2;
+                let base = if left { 0.2 } else { 0.8 };
+                image.set(x, y, Vector3::repeat(base + rng.gen_range(-0.1..0.1)));
+                aovs.depth.push(1.0);
+                aovs.object_id.push(if left { 0.0 } else { 1.0 });
+                aovs.shadow.push(0.0);
+                aovs.normal.set(x, y, Vector3::new(0.0, 0.0, 1.0));
+                aovs.albedo.set(x, y, Vector3::repeat(base));
+            }
+        }
+
+        let denoised = denoise(&image, &aovs, &DenoiseSettings::default());
+
+        let error = |framebuffer: &Framebuffer| {
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let expected = if x < width / 2 { 0.2 } else { 0.8 };
+                    (framebuffer.get(x, y).x - expected).abs()
+                })
+                .sum::<f32>()
+                / (width * height) as f32
+        };
+
+        assert!(error(&denoised) < 0.5 * error(&image));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::framebuffer::Framebuffer;
+use std::fs;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+// single part scanline file, format version 2
+const VERSION: u32 = 2;
+const PIXEL_TYPE_FLOAT: i32 = 2;
+const NO_COMPRESSION: u8 = 0;
+const INCREASING_Y: u8 = 0;
+
+/// Float image with any number of named channels, written as an uncompressed scanline OpenEXR.
+///
+/// Layers follow the usual `layer.channel` naming, e.g. `normal.X`, so compositing tools group
+/// them; the beauty pass uses plain `R`, `G` and `B`.
+#[derive(Debug, Clone)]
+pub struct ExrImage {
+    pub width: u32,
+    pub height: u32,
+    channels: Vec<(String, Vec<f32>)>,
+}
+
+impl ExrImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        ExrImage {
+            width,
+            height,
+            channels: Vec::new(),
+        }
+    }
+
+    /// Adds one channel of `width * height` values, rows from top to bottom.
+    pub fn add_channel(&mut self, name: &str, values: Vec<f32>) -> Result<(), String> {
+        if values.len() != (self.width * self.height) as usize {
+            return Err(format!(
+                "channel {} has {} values, expected {}",
+                name,
+                values.len(),
+                self.width * self.height
+            ));
+        }
+        if name.is_empty() || name.len() > 31 {
+            return Err(format!("invalid channel name: {:?}", name));
+        }
+
+        self.channels.retain(|(existing, _)| existing != name);
+        self.channels.push((name.to_string(), values));
+        Ok(())
+    }
+
+    /// Adds the R, G and B channels of `layer`, unprefixed when `layer` is empty.
+    pub fn add_rgb_layer(&mut self, layer: &str, framebuffer: &Framebuffer) -> Result<(), String> {
+        for (component, suffix) in ["R", "G", "B"].iter().enumerate() {
+            let name = if layer.is_empty() {
+                suffix.to_string()
+            } else {
+                format!("{}.{}", layer, suffix)
+            };
+            let values = framebuffer.pixels.iter().map(|p| p[component]).collect();
+            self.add_channel(&name, values)?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_bytes()).map_err(|e| e.to_string())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // readers expect channels in alphabetical order, both in the header and in the pixels
+        let mut channels: Vec<&(String, Vec<f32>)> = self.channels.iter().collect();
+        channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+
+        let mut channel_list = Vec::new();
+        for (name, _) in channels.iter() {
+            channel_list.extend_from_slice(name.as_bytes());
+            channel_list.push(0);
+            channel_list.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+            // pLinear and three reserved bytes
+            channel_list.extend_from_slice(&[0, 0, 0, 0]);
+            // x and y sampling
+            channel_list.extend_from_slice(&1i32.to_le_bytes());
+            channel_list.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channel_list.push(0);
+
+        let mut window = Vec::new();
+        for value in [0, 0, self.width as i32 - 1, self.height as i32 - 1] {
+            window.extend_from_slice(&value.to_le_bytes());
+        }
+
+        write_attribute(&mut bytes, "channels", "chlist", &channel_list);
+        write_attribute(&mut bytes, "compression", "compression", &[NO_COMPRESSION]);
+        write_attribute(&mut bytes, "dataWindow", "box2i", &window);
+        write_attribute(&mut bytes, "displayWindow", "box2i", &window);
+        write_attribute(&mut bytes, "lineOrder", "lineOrder", &[INCREASING_Y]);
+        write_attribute(&mut bytes, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        write_attribute(&mut bytes, "screenWindowCenter", "v2f", &[0; 8]);
+        write_attribute(
+            &mut bytes,
+            "screenWindowWidth",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        bytes.push(0);
+
+        // one scanline per chunk: y, data size, then the row of every channel in turn
+        let row_size = 4 * self.width as usize * channels.len();
+        let chunk_size = 8 + row_size;
+        let table_end = bytes.len() + 8 * self.height as usize;
+        for y in 0..self.height as usize {
+            bytes.extend_from_slice(&((table_end + y * chunk_size) as u64).to_le_bytes());
+        }
+
+        for y in 0..self.height as usize {
+            bytes.extend_from_slice(&(y as i32).to_le_bytes());
+            bytes.extend_from_slice(&(row_size as i32).to_le_bytes());
+            for (_, values) in channels.iter() {
+                let row = &values[y * self.width as usize..(y + 1) * self.width as usize];
+                for value in row {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+
+        bytes
+    }
+}
+
+fn write_attribute(bytes: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    bytes.extend_from_slice(name.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(kind.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channels_are_written_in_alphabetical_order() {
+        let mut image = ExrImage::new(1, 1);
+        image.add_channel("Z", vec![3.0]).unwrap();
+        image.add_channel("A", vec![1.0]).unwrap();
+        image.add_channel("B", vec![2.0]).unwrap();
+
+        let bytes = image.to_bytes();
+        let pixels: Vec<f32> = bytes[bytes.len() - 12..]
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+
+        assert_eq!(&bytes[..4], &MAGIC);
+        assert_eq!(pixels, vec![1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_channel_size_is_checked() {
+        let mut image = ExrImage::new(2, 2);
+        assert!(image.add_channel("Y", vec![0.0; 3]).is_err());
+        assert!(image.add_channel("Y", vec![0.0; 4]).is_ok());
+    }
+}
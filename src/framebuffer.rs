@@ -0,0 +1,237 @@
+use crate::exr::ExrImage;
+use image::{ImageBuffer, Rgb};
+use nalgebra::Vector3;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitDepth {
+    Eight,
+    /// Only PNG and PPM can store 16 bits per channel.
+    Sixteen,
+}
+
+/// Rendered image in linear color, 1.0 being white, rows from top to bottom.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Vector3<f32>>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Vector3::zeros(); (width * height) as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Vector3<f32> {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Vector3<f32>) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    /// Interleaved RGB bytes, clamped and sRGB encoded, ready for display.
+    pub fn to_srgb8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| {
+                pixel
+                    .iter()
+                    .map(|&c| quantize(linear_to_srgb(c), 255.0) as u8)
+            })
+            .collect()
+    }
+
+    pub fn to_srgb16(&self) -> Vec<u16> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| {
+                pixel
+                    .iter()
+                    .map(|&c| quantize(linear_to_srgb(c), 65535.0) as u16)
+            })
+            .collect()
+    }
+
+    /// Writes the image in the format given by the extension of `path`: png, ppm or bmp, or the
+    /// unclamped float formats exr and pfm, for which `bit_depth` doesn't matter.
+    pub fn save(&self, path: &str, bit_depth: BitDepth) -> Result<(), String> {
+        match extension(path).as_deref() {
+            Some("png") => self.save_png(path, bit_depth),
+            Some("ppm") => self.save_ppm(path, bit_depth),
+            Some("bmp") if bit_depth == BitDepth::Eight => self.save_bmp(path),
+            Some("bmp") => Err("BMP output only supports 8 bits per channel".to_string()),
+            Some("exr") => {
+                let mut image = ExrImage::new(self.width, self.height);
+                image.add_rgb_layer("", self)?;
+                image.save(path)
+            }
+            Some("pfm") => self.save_pfm(path),
+            _ => Err(format!("unsupported image format: {}", path)),
+        }
+    }
+
+    pub fn save_png(&self, path: &str, bit_depth: BitDepth) -> Result<(), String> {
+        match bit_depth {
+            BitDepth::Eight => {
+                ImageBuffer::<Rgb<u8>, _>::from_raw(self.width, self.height, self.to_srgb8())
+                    .ok_or("framebuffer size doesn't match its pixels")?
+                    .save(path)
+            }
+            BitDepth::Sixteen => {
+                ImageBuffer::<Rgb<u16>, _>::from_raw(self.width, self.height, self.to_srgb16())
+                    .ok_or("framebuffer size doesn't match its pixels")?
+                    .save(path)
+            }
+        }
+        .map_err(|e| e.to_string())
+    }
+
+    /// Binary (P6) portable pixmap, 16-bit samples are big-endian as the format requires.
+    pub fn save_ppm(&self, path: &str, bit_depth: BitDepth) -> Result<(), String> {
+        let (max_value, data) = match bit_depth {
+            BitDepth::Eight => (255, self.to_srgb8()),
+            BitDepth::Sixteen => (
+                65535,
+                self.to_srgb16()
+                    .iter()
+                    .flat_map(|value| value.to_be_bytes())
+                    .collect(),
+            ),
+        };
+
+        let mut bytes = format!("P6\n{} {}\n{}\n", self.width, self.height, max_value).into_bytes();
+        bytes.extend_from_slice(&data);
+        fs::write(path, bytes).map_err(|e| e.to_string())
+    }
+
+    /// Portable float map of the linear values, little-endian, rows bottom to top.
+    pub fn save_pfm(&self, path: &str) -> Result<(), String> {
+        // a negative scale marks little-endian data
+        let mut bytes = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
+
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                for c in self.get(x, y).iter() {
+                    bytes.extend_from_slice(&c.to_le_bytes());
+                }
+            }
+        }
+
+        fs::write(path, bytes).map_err(|e| e.to_string())
+    }
+
+    pub fn save_bmp(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.bmp_bytes()).map_err(|e| e.to_string())
+    }
+
+    /// Uncompressed 24-bit BMP: BGR pixels, rows bottom to top, each padded to 4 bytes.
+    fn bmp_bytes(&self) -> Vec<u8> {
+        const HEADER_SIZE: u32 = 14 + 40;
+        let row_size = (3 * self.width + 3) & !3;
+        let image_size = row_size * self.height;
+        let srgb = self.to_srgb8();
+
+        let mut bytes = Vec::with_capacity((HEADER_SIZE + image_size) as usize);
+        // file header
+        bytes.extend_from_slice(b"BM");
+        bytes.extend_from_slice(&(HEADER_SIZE + image_size).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+        // BITMAPINFOHEADER
+        bytes.extend_from_slice(&40u32.to_le_bytes());
+        bytes.extend_from_slice(&(self.width as i32).to_le_bytes());
+        bytes.extend_from_slice(&(self.height as i32).to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&24u16.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&image_size.to_le_bytes());
+        // 72 dpi
+        bytes.extend_from_slice(&2835i32.to_le_bytes());
+        bytes.extend_from_slice(&2835i32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+
+        for y in (0..self.height).rev() {
+            let row_start = bytes.len();
+            for x in 0..self.width {
+                let i = 3 * (y * self.width + x) as usize;
+                bytes.extend_from_slice(&[srgb[i + 2], srgb[i + 1], srgb[i]]);
+            }
+            bytes.resize(row_start + row_size as usize, 0);
+        }
+
+        bytes
+    }
+}
+
+/// Whether `path` names one of the float formats, which keep the unclamped linear values.
+pub fn is_float_format(path: &str) -> bool {
+    matches!(extension(path).as_deref(), Some("exr") | Some("pfm"))
+}
+
+fn extension(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+}
+
+/// sRGB transfer function, linear [0, 1] to encoded [0, 1].
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        12.92 * c.max(0.0)
+    } else {
+        1.055 * c.min(1.0).powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Inverse of `linear_to_srgb`.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c.max(0.0) / 12.92
+    } else {
+        ((c.min(1.0) + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn quantize(c: f32, max_value: f32) -> f32 {
+    (c.clamp(0.0, 1.0) * max_value).round()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for i in 0..=100 {
+            let c = i as f32 / 100.0;
+            assert!((srgb_to_linear(linear_to_srgb(c)) - c).abs() < 1e-5);
+        }
+        assert!((linear_to_srgb(0.5) - 0.7354).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_out_of_range_values_are_clamped() {
+        let mut framebuffer = Framebuffer::new(2, 1);
+        framebuffer.set(0, 0, Vector3::new(-1.0, 0.0, 0.5));
+        framebuffer.set(1, 0, Vector3::new(1.0, 7.5, f32::INFINITY));
+
+        assert_eq!(framebuffer.to_srgb8(), vec![0, 0, 188, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_bmp_rows_are_padded() {
+        let bytes = Framebuffer::new(3, 2).bmp_bytes();
+        // 3 pixels take 9 bytes, padded to 12 per row
+        assert_eq!(bytes.len(), 54 + 2 * 12);
+        assert_eq!(&bytes[..2], b"BM");
+    }
+}
@@ -0,0 +1,400 @@
+use crate::intersections::{tangent_frame, Intersectable, IntersectionRecord};
+use crate::materials::Material;
+use crate::mesh::intersect_triangle;
+use crate::ray::Ray;
+use nalgebra::{Vector2, Vector3};
+
+/// Terrain given as a regular grid of height samples.
+///
+/// The grid spans `size.x` by `size.z` world units starting at `origin`, sample values
+/// (0.0 to 1.0) are scaled by `size.y`. Rays walk the grid cell by cell and only test the two
+/// triangles of cells whose highest sample the ray can reach.
+pub struct Heightfield {
+    pub origin: Vector3<f32>,
+    pub size: Vector3<f32>,
+    pub material: Material,
+    width: usize,
+    depth: usize,
+    heights: Vec<f32>,
+    normals: Vec<Vector3<f32>>,
+    cell_max_heights: Vec<f32>,
+}
+
+impl Heightfield {
+    /// `heights` is indexed as `heights[z][x]`, every row must have the same length.
+    pub fn new(
+        origin: Vector3<f32>,
+        size: Vector3<f32>,
+        heights: Vec<Vec<f32>>,
+        material: Material,
+    ) -> Self {
+        let depth = heights.len();
+        let width = heights.first().map_or(0, |row| row.len());
+        assert!(
+            width >= 2 && depth >= 2,
+            "heightfield needs at least 2x2 samples"
+        );
+        assert!(
+            heights.iter().all(|row| row.len() == width),
+            "heightfield rows must have equal length"
+        );
+
+        let mut heightfield = Heightfield {
+            origin,
+            size,
+            material,
+            width,
+            depth,
+            heights: heights.into_iter().flatten().collect(),
+            normals: Vec::new(),
+            cell_max_heights: Vec::new(),
+        };
+
+        heightfield.normals = (0..depth)
+            .flat_map(|z| (0..width).map(move |x| (x, z)))
+            .map(|(x, z)| heightfield.compute_normal(x, z))
+            .collect();
+
+        heightfield.cell_max_heights = (0..depth - 1)
+            .flat_map(|z| (0..width - 1).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                heightfield
+                    .vertex(x, z)
+                    .y
+                    .max(heightfield.vertex(x + 1, z).y)
+                    .max(heightfield.vertex(x, z + 1).y)
+                    .max(heightfield.vertex(x + 1, z + 1).y)
+            })
+            .collect();
+
+        heightfield
+    }
+
+    /// Loads a grayscale (or color, converted to luminance) image, white being the highest point.
+    pub fn from_image(
+        path: &str,
+        origin: Vector3<f32>,
+        size: Vector3<f32>,
+        material: Material,
+    ) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("{}: {}", path, e))?
+            .into_luma16();
+        if image.width() < 2 || image.height() < 2 {
+            return Err(format!("{}: heightfield needs at least 2x2 pixels", path));
+        }
+
+        let heights = image
+            .rows()
+            .map(|row| row.map(|pixel| pixel[0] as f32 / u16::MAX as f32).collect())
+            .collect();
+
+        Ok(Heightfield::new(origin, size, heights, material))
+    }
+
+    fn cell_size(&self) -> (f32, f32) {
+        (
+            self.size.x / (self.width - 1) as f32,
+            self.size.z / (self.depth - 1) as f32,
+        )
+    }
+
+    fn height(&self, x: usize, z: usize) -> f32 {
+        self.heights[z * self.width + x]
+    }
+
+    fn vertex(&self, x: usize, z: usize) -> Vector3<f32> {
+        let (cell_x, cell_z) = self.cell_size();
+        self.origin
+            + Vector3::new(
+                x as f32 * cell_x,
+                self.height(x, z) * self.size.y,
+                z as f32 * cell_z,
+            )
+    }
+
+    fn compute_normal(&self, x: usize, z: usize) -> Vector3<f32> {
+        // central differences, one-sided at the borders
+        let (x0, x1) = (x.saturating_sub(1), (x + 1).min(self.width - 1));
+        let (z0, z1) = (z.saturating_sub(1), (z + 1).min(self.depth - 1));
+        let (cell_x, cell_z) = self.cell_size();
+
+        let dh_dx =
+            (self.height(x1, z) - self.height(x0, z)) * self.size.y / ((x1 - x0) as f32 * cell_x);
+        let dh_dz =
+            (self.height(x, z1) - self.height(x, z0)) * self.size.y / ((z1 - z0) as f32 * cell_z);
+
+        Vector3::new(-dh_dx, 1.0, -dh_dz).normalize()
+    }
+
+    /// Ray parameter range inside the bounding box of the terrain.
+    fn bounding_interval(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut t_enter = t_min;
+        let mut t_exit = t_max;
+
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction()[axis];
+            let mut t0 = (self.origin[axis] - ray.origin()[axis]) * inverse_direction;
+            let mut t1 =
+                (self.origin[axis] + self.size[axis] - ray.origin()[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            // NaN (ray parallel to and on a slab plane) leaves the interval unchanged
+            t_enter = if t0 > t_enter { t0 } else { t_enter };
+            t_exit = if t1 < t_exit { t1 } else { t_exit };
+            if t_exit < t_enter {
+                return None;
+            }
+        }
+
+        Some((t_enter, t_exit))
+    }
+
+    /// Intersects the two triangles of cell (x, z), returns ray parameter and smooth normal.
+    fn intersect_cell(
+        &self,
+        ray: &Ray,
+        x: usize,
+        z: usize,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(f32, Vector3<f32>)> {
+        let corners = [(x, z), (x + 1, z), (x, z + 1), (x + 1, z + 1)];
+        let triangles = [[0, 1, 2], [1, 3, 2]];
+
+        let mut nearest: Option<(f32, Vector3<f32>)> = None;
+        let mut nearest_t = t_max;
+
+        for triangle in triangles.iter() {
+            let [a, b, c] = triangle.map(|i| corners[i]);
+            let hit = intersect_triangle(
+                ray,
+                self.vertex(a.0, a.1),
+                self.vertex(b.0, b.1),
+                self.vertex(c.0, c.1),
+            );
+
+            if let Some((t, u, v)) = hit {
+                if t_min < t && t < nearest_t {
+                    let normal = (1.0 - u - v) * self.normals[a.1 * self.width + a.0]
+                        + u * self.normals[b.1 * self.width + b.0]
+                        + v * self.normals[c.1 * self.width + c.0];
+                    nearest_t = t;
+                    nearest = Some((t, normal.normalize()));
+                }
+            }
+        }
+
+        nearest
+    }
+}
+
+impl Intersectable for Heightfield {
+    fn center(&self) -> Vector3<f32> {
+        self.origin + self.size / 2.0
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<IntersectionRecord> {
+        let (t_enter, t_exit) = self.bounding_interval(ray, t_min, t_max)?;
+        let (cell_x, cell_z) = self.cell_size();
+        let direction = ray.direction();
+
+        // 2D DDA over the grid cells in x/z
+        let entry = ray.point_at_parameter(t_enter) - self.origin;
+        let last_x = (self.width - 2) as i64;
+        let last_z = (self.depth - 2) as i64;
+        let mut x = ((entry.x / cell_x).floor() as i64).clamp(0, last_x);
+        let mut z = ((entry.z / cell_z).floor() as i64).clamp(0, last_z);
+
+        let step_x: i64 = if direction.x >= 0.0 { 1 } else { -1 };
+        let step_z: i64 = if direction.z >= 0.0 { 1 } else { -1 };
+        // a ray parallel to an axis never crosses its cell boundaries, also for -0.0 which
+        // would otherwise step forward but divide to -inf
+        let t_delta = |cell_size: f32, axis: usize| {
+            if direction[axis] == 0.0 {
+                f32::INFINITY
+            } else {
+                (cell_size / direction[axis]).abs()
+            }
+        };
+        let t_delta_x = t_delta(cell_x, 0);
+        let t_delta_z = t_delta(cell_z, 2);
+
+        let next_boundary = |cell: i64, step: i64, cell_size: f32, axis: usize| {
+            if direction[axis] == 0.0 {
+                return f32::INFINITY;
+            }
+            let boundary = self.origin[axis] + (cell + step.max(0)) as f32 * cell_size;
+            (boundary - ray.origin()[axis]) / direction[axis]
+        };
+        let mut t_next_x = next_boundary(x, step_x, cell_x, 0);
+        let mut t_next_z = next_boundary(z, step_z, cell_z, 2);
+
+        let mut t_cell_enter = t_enter;
+        while t_cell_enter <= t_exit {
+            let t_cell_exit = t_next_x.min(t_next_z).min(t_exit);
+
+            let lowest_ray_height = ray
+                .point_at_parameter(t_cell_enter)
+                .y
+                .min(ray.point_at_parameter(t_cell_exit).y);
+            let cell_index = z as usize * (self.width - 1) + x as usize;
+
+            if lowest_ray_height <= self.cell_max_heights[cell_index] {
+                if let Some((t, normal)) =
+                    self.intersect_cell(ray, x as usize, z as usize, t_min, t_max)
+                {
+                    let p = ray.point_at_parameter(t);
+                    let local = p - self.origin;
+                    // u follows x, v runs against z like the image rows run along it, so
+                    // textures made from the height image line up with it
+                    let uv = Vector2::new(local.x / self.size.x, 1.0 - local.z / self.size.z);
+                    let (tangent, bitangent) = tangent_frame(normal, Vector3::new(1.0, 0.0, 0.0));
+                    let bitangent = -bitangent;
+
+                    return Some(IntersectionRecord {
+                        intersection_point: t,
+                        intersection_vector: p,
+                        object_center: self.center(),
+                        object_index: 0,
+                        normal: self
+                            .material
+                            .shading_normal(uv, local, normal, tangent, bitangent),
+                        geometric_normal: normal,
+                        object_color: self.material.color_at(uv, local),
+                        object_specular: self.material.specular,
+                        object_reflective: self.material.reflective,
+                        object_refractive: self.material.refractive,
+                        object_model: self.material.model,
+                        object_emission: self.material.emission,
+                        object_coating: self.material.coating,
+                    });
+                }
+            }
+
+            if t_next_x < t_next_z {
+                x += step_x;
+                t_cell_enter = t_next_x;
+                t_next_x += t_delta_x;
+            } else {
+                z += step_z;
+                t_cell_enter = t_next_z;
+                t_next_z += t_delta_z;
+            }
+
+            if x < 0 || x > last_x || z < 0 || z > last_z {
+                break;
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::textures::{FilterMode, ImageTexture, WrapMode};
+    use std::sync::Arc;
+
+    fn flat_terrain(height: f32) -> Heightfield {
+        Heightfield::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(4.0, 1.0, 4.0),
+            vec![vec![height; 5]; 5],
+            Material::new(Vector3::new(255.0, 255.0, 255.0), 0.0, 0.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn test_heightfield_vertical_ray() {
+        let terrain = flat_terrain(0.5);
+        let r = Ray::new(Vector3::new(1.3, 3.0, 2.7), Vector3::new(0.0, -1.0, 0.0));
+
+        match terrain.intersect(&r, 0.001, f32::MAX) {
+            Some(res) => {
+                assert!((res.intersection_point - 2.5).abs() < 1e-4);
+                assert!((res.normal - Vector3::new(0.0, 1.0, 0.0)).norm() < 1e-4);
+            }
+            None => panic!("expected an intersection"),
+        }
+    }
+
+    #[test]
+    fn test_heightfield_negative_zero_direction() {
+        let terrain = flat_terrain(0.5);
+        let r = Ray::new(Vector3::new(1.3, 3.0, 2.7), Vector3::new(-0.0, -1.0, -0.0));
+
+        match terrain.intersect(&r, 0.001, f32::MAX) {
+            Some(res) => assert!((res.intersection_point - 2.5).abs() < 1e-4),
+            None => panic!("expected an intersection"),
+        }
+    }
+
+    #[test]
+    fn test_texture_from_the_height_image_lines_up() {
+        // the first rows of the image are high, the last ones low
+        let path = std::env::temp_dir().join("heightfield_test_ridge.png");
+        image::GrayImage::from_fn(8, 8, |_, y| image::Luma([if y < 4 { 255 } else { 0 }]))
+            .save(&path)
+            .unwrap();
+        let path = path.to_str().unwrap();
+        let texture =
+            ImageTexture::from_file(path, WrapMode::ClampToEdge, FilterMode::Nearest, false)
+                .unwrap();
+        let material = Material::textured(Arc::new(texture), 0.0, 0.0, 1.0);
+        let terrain = Heightfield::from_image(
+            path,
+            Vector3::zeros(),
+            Vector3::new(4.0, 1.0, 4.0),
+            material,
+        )
+        .unwrap();
+
+        for z in [0.3, 1.0, 3.0, 3.7] {
+            let r = Ray::new(Vector3::new(2.0, 3.0, z), Vector3::new(0.0, -1.0, 0.0));
+            let res = terrain.intersect(&r, 0.001, f32::MAX).unwrap();
+            let height = res.intersection_vector.y;
+            assert_eq!(res.object_color.x > 127.0, height > 0.5, "at z {}", z);
+        }
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_heightfield_grazing_ray_crosses_cells() {
+        let mut heights = vec![vec![0.0; 5]; 5];
+        heights[2][3] = 1.0;
+        let terrain = Heightfield::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(4.0, 1.0, 4.0),
+            heights,
+            Material::new(Vector3::new(255.0, 255.0, 255.0), 0.0, 0.0, 1.0),
+        );
+
+        let r = Ray::new(Vector3::new(-1.0, 0.5, 2.0), Vector3::new(1.0, 0.0, 0.0));
+
+        match terrain.intersect(&r, 0.001, f32::MAX) {
+            Some(res) => assert!((res.intersection_vector.x - 2.5).abs() < 1e-4),
+            None => panic!("expected an intersection"),
+        }
+    }
+
+    #[test]
+    fn test_heightfield_ray_above_terrain() {
+        let terrain = flat_terrain(0.5);
+        let r = Ray::new(Vector3::new(-1.0, 0.8, 2.0), Vector3::new(1.0, 0.0, 0.3));
+
+        assert!(terrain.intersect(&r, 0.001, f32::MAX).is_none());
+    }
+}
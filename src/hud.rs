@@ -0,0 +1,123 @@
+use crate::controls::OrbitCamera;
+use crate::progressive::Frame;
+use crate::stats::{ray_counts, RayCounts};
+use sdl2::gfx::primitives::DrawRenderer;
+use sdl2::pixels::Color;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::time::{Duration, Instant};
+
+// rates are averaged over this long
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+// the gfx font is 8x8 pixels
+const LINE_HEIGHT: i16 = 10;
+const MARGIN: i16 = 6;
+
+/// Statistics overlay of the viewer, toggled with H.
+pub struct Hud {
+    pub visible: bool,
+    last_counts: RayCounts,
+    last_update: Instant,
+    // counts per second over the last interval
+    rates: RayCounts,
+}
+
+impl Hud {
+    pub fn new() -> Self {
+        Hud {
+            visible: false,
+            last_counts: ray_counts(),
+            last_update: Instant::now(),
+            rates: RayCounts::default(),
+        }
+    }
+
+    /// Recomputes the rates once per interval, true when they changed and should be redrawn.
+    pub fn update(&mut self) -> bool {
+        let elapsed = self.last_update.elapsed();
+        if elapsed < UPDATE_INTERVAL {
+            return false;
+        }
+
+        let counts = ray_counts();
+        let per_second = |count: u64| (count as f64 / elapsed.as_secs_f64()) as u64;
+        let difference = counts - self.last_counts;
+        let rates = RayCounts {
+            primary: per_second(difference.primary),
+            shadow: per_second(difference.shadow),
+            secondary: per_second(difference.secondary),
+            intersection_tests: per_second(difference.intersection_tests),
+            paths: per_second(difference.paths),
+            path_segments: per_second(difference.path_segments),
+        };
+
+        self.last_counts = counts;
+        self.last_update = Instant::now();
+        let changed = rates != self.rates;
+        self.rates = rates;
+        changed
+    }
+
+    pub fn lines(&self, frame: &Frame, orbit: &OrbitCamera, mode: &str) -> Vec<String> {
+        let eye = orbit.eye();
+        let millions = |rate: u64| rate as f64 / 1e6;
+
+        vec![
+            format!(
+                "{}  pass {:.0} ms  {} samples",
+                mode,
+                frame.pass_time.as_secs_f64() * 1000.0,
+                frame.samples
+            ),
+            format!(
+                "rays/s {:.2}M: primary {:.2}M shadow {:.2}M secondary {:.2}M",
+                millions(self.rates.rays()),
+                millions(self.rates.primary),
+                millions(self.rates.shadow),
+                millions(self.rates.secondary)
+            ),
+            format!(
+                "intersection tests/s {:.2}M  path depth {:.2}",
+                millions(self.rates.intersection_tests),
+                self.rates.average_path_depth()
+            ),
+            format!("eye ({:.2}, {:.2}, {:.2})", eye.x, eye.y, eye.z),
+            format!(
+                "target ({:.2}, {:.2}, {:.2})",
+                orbit.target.x, orbit.target.y, orbit.target.z
+            ),
+            format!(
+                "yaw {:.1} pitch {:.1} distance {:.2} fov {:.1}{}",
+                orbit.yaw.to_degrees(),
+                orbit.pitch.to_degrees(),
+                orbit.distance,
+                orbit.fov,
+                if orbit.fly { "  fly" } else { "" }
+            ),
+        ]
+    }
+
+    /// Draws `lines` on a dark box in the top left corner.
+    pub fn draw(&self, canvas: &mut Canvas<Window>, lines: &[String]) -> Result<(), String> {
+        let width = lines.iter().map(|line| line.len()).max().unwrap_or(0) as i16 * 8;
+        let height = lines.len() as i16 * LINE_HEIGHT;
+        canvas.box_(
+            0,
+            0,
+            width + 2 * MARGIN,
+            height + 2 * MARGIN,
+            Color::RGBA(0, 0, 0, 160),
+        )?;
+
+        for (i, line) in lines.iter().enumerate() {
+            canvas.string(
+                MARGIN,
+                MARGIN + i as i16 * LINE_HEIGHT,
+                line,
+                Color::RGB(255, 255, 255),
+            )?;
+        }
+
+        Ok(())
+    }
+}
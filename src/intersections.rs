@@ -1,20 +1,77 @@
+use crate::layers::Coating;
+use crate::materials::{Material, ShadingModel};
 use crate::ray::Ray;
 use crate::scene::Scene;
-use sdl2::pixels::Color;
+use crate::stats::{self, RayKind};
+use rand::RngCore;
 
 use nalgebra::Vector3;
 pub struct IntersectionRecord {
     pub intersection_point: f32,
     pub intersection_vector: Vector3<f32>,
     pub object_center: Vector3<f32>,
-    pub object_color: Color,
+    // position in `Scene::objects`, filled in by `nearest_intersected_object`
+    pub object_index: usize,
+    // shading normal, includes normal/bump mapping
+    pub normal: Vector3<f32>,
+    pub geometric_normal: Vector3<f32>,
+    // linear 0-255, kept in floats so dark texture values aren't rounded to black
+    pub object_color: Vector3<f32>,
     pub object_specular: f32,
     pub object_reflective: f32,
     pub object_refractive: f32,
+    pub object_model: ShadingModel,
+    pub object_emission: Vector3<f32>,
+    pub object_coating: Coating,
 }
-pub trait Intersectable: Sync {
+pub trait Intersectable: Send + Sync {
     fn center(&self) -> Vector3<f32>;
     fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<IntersectionRecord>;
+    fn material(&self) -> &Material;
+    /// For editing the look while the viewer is running.
+    fn material_mut(&mut self) -> &mut Material;
+
+    /// Surface area, only needed by shapes that can be sampled as light sources.
+    fn area(&self) -> f32 {
+        0.0
+    }
+
+    /// Uniformly distributed point on the surface and the outward normal there,
+    /// `None` for shapes that can't be sampled.
+    fn sample_surface(&self, _rng: &mut dyn RngCore) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        None
+    }
+
+    /// Fraction of light passing through the object between `t_min` and `t_max` along `ray`.
+    /// Surfaces are opaque, volumes let part of the light through.
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        match self.intersect(ray, t_min, t_max) {
+            Some(_) => 0.0,
+            None => 1.0,
+        }
+    }
+}
+
+/// Completes a tangent frame around `normal`, starting from a tangent direction that does not
+/// have to be perpendicular. Returns (tangent, bitangent) with bitangent = tangent x normal.
+pub fn tangent_frame(
+    normal: Vector3<f32>,
+    tangent_hint: Vector3<f32>,
+) -> (Vector3<f32>, Vector3<f32>) {
+    let mut tangent = tangent_hint - normal * normal.dot(&tangent_hint);
+
+    if tangent.norm_squared() < 1e-12 {
+        // degenerate hint (e.g. sphere poles), any perpendicular direction will do
+        let axis = if normal.x.abs() < 0.9 {
+            Vector3::new(1.0, 0.0, 0.0)
+        } else {
+            Vector3::new(0.0, 1.0, 0.0)
+        };
+        tangent = axis - normal * normal.dot(&axis);
+    }
+
+    let tangent = tangent.normalize();
+    (tangent, tangent.cross(&normal))
 }
 
 pub fn nearest_intersected_object<'a>(
@@ -25,10 +82,12 @@ pub fn nearest_intersected_object<'a>(
 ) -> Option<IntersectionRecord> {
     let mut nearest_object_distance = max_distance;
     let mut intersect_anything: Option<IntersectionRecord> = None;
+    stats::count_intersection_tests(scene.objects.len());
 
-    for obj in scene.objects.iter() {
-        if let Some(intersection) = obj.intersect(ray, min_distance, nearest_object_distance) {
+    for (index, obj) in scene.objects.iter().enumerate() {
+        if let Some(mut intersection) = obj.intersect(ray, min_distance, nearest_object_distance) {
             nearest_object_distance = intersection.intersection_point;
+            intersection.object_index = index;
             intersect_anything = Some(intersection);
         }
     }
@@ -36,29 +95,46 @@ pub fn nearest_intersected_object<'a>(
     intersect_anything
 }
 
+/// Fraction of light getting through all objects along a shadow ray, zero when blocked.
+pub fn visibility(scene: &Scene, ray: &Ray, min_distance: f32, max_distance: f32) -> f32 {
+    let mut visibility = 1.0;
+    stats::count_ray(RayKind::Shadow);
+
+    for (tested, obj) in scene.objects.iter().enumerate() {
+        visibility *= obj.transmittance(ray, min_distance, max_distance);
+        if visibility <= 0.0 {
+            stats::count_intersection_tests(tested + 1);
+            return 0.0;
+        }
+    }
+
+    stats::count_intersection_tests(scene.objects.len());
+    visibility
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{ray::Ray, scene::Scene};
     use nalgebra::Vector3;
-    use sdl2::pixels::Color;
 
-    use crate::intersections::{nearest_intersected_object, ray_sphere_intersection, Sphere};
+    use crate::intersections::{nearest_intersected_object, Intersectable};
+    use crate::shapes::Sphere;
+
+    fn sphere(center: Vector3<f32>, radius: f32, color: Vector3<f32>) -> Sphere {
+        Sphere::new(center, radius, color, -1.0, 0.0, 1.0)
+    }
 
     #[test]
     fn test_ray_sphere_two_intersections() {
         let r1 = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
 
-        let sphere = Sphere {
-            center: Vector3::new(4.0, 0.0, 0.0),
-            radius: 1.0,
-            color: Vector3::new(0.0, 0.0, 0.0),
-        };
+        let sphere = sphere(Vector3::new(4.0, 0.0, 0.0), 1.0, Vector3::zeros());
 
-        let res = ray_sphere_intersection(&r1, &sphere);
+        let res = sphere.intersect(&r1, 0.001, f32::MAX);
 
         match res {
-            Some(val) => assert_eq!(val, 3.0 as f32),
-            None => assert!(false),
+            Some(val) => assert_eq!(val.intersection_point, 3.0),
+            None => panic!("expected an intersection"),
         }
     }
 
@@ -66,17 +142,13 @@ mod tests {
     fn ray_sphere_intersection_intersection() {
         let r2 = Ray::new(Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
 
-        let sphere = Sphere {
-            center: Vector3::new(4.0, 0.0, 0.0),
-            radius: 1.0,
-            color: Vector3::new(0.0, 0.0, 0.0),
-        };
+        let sphere = sphere(Vector3::new(4.0, 0.0, 0.0), 1.0, Vector3::zeros());
 
-        let res = ray_sphere_intersection(&r2, &sphere);
+        let res = sphere.intersect(&r2, 0.001, f32::MAX);
 
         match res {
-            Some(val) => assert_eq!(val, 4.0 as f32),
-            None => assert!(false),
+            Some(val) => assert_eq!(val.intersection_point, 4.0),
+            None => panic!("expected an intersection"),
         }
     }
 
@@ -84,35 +156,31 @@ mod tests {
     fn ray_sphere_intersection_no_intersection() {
         let r3 = Ray::new(Vector3::new(0.0, 0.0, 2.0), Vector3::new(1.0, 0.0, 0.0));
 
-        let sphere = Sphere {
-            center: Vector3::new(4.0, 0.0, 0.0),
-            radius: 1.0,
-            color: Vector3::new(0.0, 0.0, 0.0),
-        };
+        let sphere = sphere(Vector3::new(4.0, 0.0, 0.0), 1.0, Vector3::zeros());
 
-        let res = ray_sphere_intersection(&r3, &sphere);
+        assert!(sphere.intersect(&r3, 0.001, f32::MAX).is_none());
+    }
 
-        match res {
-            None => assert!(true),
-            Some(_) => assert!(false),
-        }
+    #[test]
+    fn test_dark_colors_keep_their_precision() {
+        let r = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        let color = Vector3::new(0.4, 0.05, 1.5);
+
+        let res = sphere(Vector3::new(4.0, 0.0, 0.0), 1.0, color).intersect(&r, 0.001, f32::MAX);
+
+        assert_eq!(res.unwrap().object_color, color);
     }
 
     #[test]
     fn test_nearest_object_trivial() {
         let mut scene = Scene::default();
 
-        scene.push(Sphere {
-            center: Vector3::new(7.0, 0.0, 0.0),
-            radius: 1.2,
-            color: Vector3::new(255.0, 255.0, 255.0),
-        });
-
-        scene.push(Sphere {
-            center: Vector3::new(4.0, 0.0, 0.0),
-            radius: 1.0,
-            color: Vector3::new(0.0, 0.0, 0.0),
-        });
+        scene.push(sphere(
+            Vector3::new(7.0, 0.0, 0.0),
+            1.2,
+            Vector3::new(255.0, 255.0, 255.0),
+        ));
+        scene.push(sphere(Vector3::new(4.0, 0.0, 0.0), 1.0, Vector3::zeros()));
 
         let r = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
 
@@ -120,13 +188,11 @@ mod tests {
 
         match res {
             Some(intersection) => {
-                assert_eq!(
-                    intersection.object_color,
-                    Color::RGB(0.0 as u8, 0.0 as u8, 0.0 as u8)
-                );
+                assert_eq!(intersection.object_color, Vector3::zeros());
+                assert_eq!(intersection.object_index, 1);
                 assert_eq!(intersection.intersection_point, 3.0);
             }
-            None => assert!(false),
+            None => panic!("expected an intersection"),
         }
     }
 
@@ -134,25 +200,11 @@ mod tests {
     fn test_nearest_object_no_intersection() {
         let mut scene = Scene::default();
 
-        scene.push(Sphere {
-            center: Vector3::new(7.0, 0.0, 0.0),
-            radius: 1.2,
-            color: Vector3::new(0.0, 0.0, 0.0),
-        });
-
-        scene.push(Sphere {
-            center: Vector3::new(4.0, 0.0, 0.0),
-            radius: 1.0,
-            color: Vector3::new(0.0, 0.0, 0.0),
-        });
+        scene.push(sphere(Vector3::new(7.0, 0.0, 0.0), 1.2, Vector3::zeros()));
+        scene.push(sphere(Vector3::new(4.0, 0.0, 0.0), 1.0, Vector3::zeros()));
 
         let r = Ray::new(Vector3::new(0.0, 0.0, 3.0), Vector3::new(1.0, 0.0, 0.0));
 
-        let res = nearest_intersected_object(&scene, &r, 0.01, 10.0);
-
-        match res {
-            Some(_) => assert!(false),
-            None => assert!(true),
-        }
+        assert!(nearest_intersected_object(&scene, &r, 0.01, 10.0).is_none());
     }
 }
@@ -0,0 +1,161 @@
+use crate::bsdf::fresnel_dielectric;
+use crate::spectral::{wavelength_to_rgb, MAX_WAVELENGTH, MIN_WAVELENGTH};
+use nalgebra::Vector3;
+use std::f32::consts::PI;
+
+// typical lacquer and car paint clear coats
+pub const CLEAR_COAT_IOR: f32 = 1.5;
+// wavelengths averaged when a thin film is shaded in RGB
+const THIN_FILM_SAMPLES: u32 = 16;
+
+/// Transparent varnish over the base material, reflecting with Fresnel of index 1.5.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClearCoat {
+    /// How much of the coat is present (0-1).
+    pub strength: f32,
+    pub roughness: f32,
+}
+
+/// Film a few hundred nanometers thick whose reflections interfere, like soap or oil.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThinFilm {
+    /// Thickness in nanometers.
+    pub thickness: f32,
+    pub ior: f32,
+}
+
+/// Layers on top of a material's shading model. A thin film sits on the clear coat if there is
+/// one, otherwise directly on the base.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Coating {
+    pub clear_coat: Option<ClearCoat>,
+    pub thin_film: Option<ThinFilm>,
+    /// Set while rendering a single wavelength, the film is then evaluated only there.
+    pub wavelength: Option<f32>,
+}
+
+impl Coating {
+    pub fn is_empty(&self) -> bool {
+        self.clear_coat.is_none() && self.thin_film.is_none()
+    }
+
+    pub fn strength(&self) -> f32 {
+        self.clear_coat
+            .map_or(1.0, |coat| coat.strength.clamp(0.0, 1.0))
+    }
+
+    pub fn roughness(&self) -> f32 {
+        self.clear_coat
+            .map_or(0.0, |coat| coat.roughness.clamp(0.0, 1.0))
+    }
+
+    /// Reflectance of the top interface, `base_ior` is the index right under the film when
+    /// there is no clear coat.
+    pub fn fresnel(&self, cos_theta: f32, base_ior: f32) -> Vector3<f32> {
+        let substrate_ior = match self.clear_coat {
+            Some(_) => CLEAR_COAT_IOR,
+            None => base_ior,
+        };
+
+        let reflectance = match (self.thin_film, self.wavelength) {
+            (Some(film), Some(wavelength)) => Vector3::repeat(thin_film_reflectance(
+                cos_theta,
+                film,
+                substrate_ior,
+                wavelength,
+            )),
+            (Some(film), None) => thin_film_rgb(cos_theta, film, substrate_ior),
+            (None, _) => Vector3::repeat(fresnel_dielectric(cos_theta, 1.0, substrate_ior)),
+        };
+
+        reflectance * self.strength()
+    }
+}
+
+/// Reflectance of a film on a substrate seen from air at `wavelength` (nm), unpolarized.
+///
+/// Airy summation of the waves reflected inside the film, with real indices only.
+pub fn thin_film_reflectance(
+    cos_theta: f32,
+    film: ThinFilm,
+    substrate_ior: f32,
+    wavelength: f32,
+) -> f32 {
+    let cos_1 = cos_theta.clamp(0.0, 1.0);
+    let sin_1 = (1.0 - cos_1 * cos_1).sqrt();
+    let (n1, n2, n3) = (1.0, film.ior, substrate_ior);
+
+    let sin_2 = n1 / n2 * sin_1;
+    let sin_3 = n1 / n3 * sin_1;
+    if sin_2 >= 1.0 || sin_3 >= 1.0 {
+        return 1.0;
+    }
+    let cos_2 = (1.0 - sin_2 * sin_2).sqrt();
+    let cos_3 = (1.0 - sin_3 * sin_3).sqrt();
+
+    // phase difference between the waves reflected at the top and at the bottom of the film
+    let delta = 4.0 * PI * n2 * film.thickness * cos_2 / wavelength;
+
+    let airy = |r12: f32, r23: f32| {
+        let interference = 2.0 * r12 * r23 * delta.cos();
+        (r12 * r12 + r23 * r23 + interference) / (1.0 + r12 * r12 * r23 * r23 + interference)
+    };
+
+    let s = airy(
+        (n1 * cos_1 - n2 * cos_2) / (n1 * cos_1 + n2 * cos_2),
+        (n2 * cos_2 - n3 * cos_3) / (n2 * cos_2 + n3 * cos_3),
+    );
+    let p = airy(
+        (n2 * cos_1 - n1 * cos_2) / (n2 * cos_1 + n1 * cos_2),
+        (n3 * cos_2 - n2 * cos_3) / (n3 * cos_2 + n2 * cos_3),
+    );
+
+    (0.5 * (s + p)).clamp(0.0, 1.0)
+}
+
+/// Color of the film reflection, the reflectance integrated against the color matching curves.
+pub fn thin_film_rgb(cos_theta: f32, film: ThinFilm, substrate_ior: f32) -> Vector3<f32> {
+    let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / THIN_FILM_SAMPLES as f32;
+
+    let rgb = (0..THIN_FILM_SAMPLES)
+        .map(|i| MIN_WAVELENGTH + (i as f32 + 0.5) * step)
+        .map(|wavelength| {
+            let reflectance = thin_film_reflectance(cos_theta, film, substrate_ior, wavelength);
+            wavelength_to_rgb(Vector3::repeat(reflectance), wavelength)
+        })
+        .sum::<Vector3<f32>>()
+        / THIN_FILM_SAMPLES as f32;
+
+    rgb.map(|c| c.clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vanishing_film_is_plain_fresnel() {
+        let film = ThinFilm {
+            thickness: 0.0,
+            ior: 1.33,
+        };
+
+        for cos_theta in [1.0, 0.7, 0.3] {
+            let expected = fresnel_dielectric(cos_theta, 1.0, 1.5);
+            let reflectance = thin_film_reflectance(cos_theta, film, 1.5, 550.0);
+            assert!((reflectance - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_quarter_wave_film_cancels_reflection() {
+        // anti-reflective coating: index sqrt(n_substrate), a quarter wavelength thick
+        let ior = 1.5f32.sqrt();
+        let film = ThinFilm {
+            thickness: 550.0 / (4.0 * ior),
+            ior,
+        };
+
+        assert!(thin_film_reflectance(1.0, film, 1.5, 550.0) < 1e-4);
+    }
+}
@@ -4,12 +4,19 @@ use sdl2::pixels::Color;
 pub enum LightType {
     Ambient,
     Positional,
+    Spot,
 }
 
-pub trait Light: Sync {
+pub trait Light: Send + Sync {
     fn light_type(&self) -> LightType;
     fn intensity(&self) -> f32;
     fn center(&self) -> Vector3<f32>;
+    fn color(&self) -> Vector3<f32>;
+
+    /// Fraction of the intensity sent towards `p`, lights without a direction send it everywhere.
+    fn attenuation(&self, _p: Vector3<f32>) -> f32 {
+        1.0
+    }
 }
 
 pub struct PositionalLight {
@@ -58,6 +65,74 @@ impl Light for PositionalLight {
     fn center(&self) -> Vector3<f32> {
         self.center
     }
+
+    fn color(&self) -> Vector3<f32> {
+        self.color
+    }
+}
+
+/// Positional light limited to a cone, with a smooth falloff towards the cone's edge.
+pub struct SpotLight {
+    pub center: Vector3<f32>,
+    pub direction: Vector3<f32>,
+    pub intensity: f32,
+    pub color: Vector3<f32>,
+    // cosines of the angles where the falloff starts and where the light ends
+    cos_inner: f32,
+    cos_outer: f32,
+}
+
+impl SpotLight {
+    /// `cone_angle` is the half angle of the cone in degrees, `softness` (0-1) the part of it
+    /// over which the light fades out.
+    pub fn new(
+        center: Vector3<f32>,
+        direction: Vector3<f32>,
+        cone_angle: f32,
+        softness: f32,
+        intensity: f32,
+        color: Vector3<f32>,
+    ) -> Self {
+        let outer = cone_angle.to_radians();
+        let inner = outer * (1.0 - softness.clamp(0.0, 1.0));
+
+        SpotLight {
+            center,
+            direction: direction.normalize(),
+            intensity,
+            color,
+            cos_inner: inner.cos(),
+            cos_outer: outer.cos(),
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn light_type(&self) -> LightType {
+        LightType::Spot
+    }
+
+    fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    fn center(&self) -> Vector3<f32> {
+        self.center
+    }
+
+    fn color(&self) -> Vector3<f32> {
+        self.color
+    }
+
+    fn attenuation(&self, p: Vector3<f32>) -> f32 {
+        let cos_angle = self.direction.dot(&(p - self.center).normalize());
+        if cos_angle >= self.cos_inner {
+            return 1.0;
+        }
+
+        let t = ((cos_angle - self.cos_outer) / (self.cos_inner - self.cos_outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
 }
 
 pub struct AmbientLight {
@@ -105,4 +180,8 @@ impl Light for AmbientLight {
     fn center(&self) -> Vector3<f32> {
         Vector3::new(0.0, 0.0, 0.0)
     }
+
+    fn color(&self) -> Vector3<f32> {
+        self.color
+    }
 }
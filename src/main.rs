@@ -1,10 +1,34 @@
+mod aov;
+mod bsdf;
 mod camera;
 mod colors;
+mod controls;
+mod denoise;
+mod exr;
+mod framebuffer;
+mod heightfield;
+mod hud;
 mod intersections;
+mod layers;
 mod lights;
+mod materials;
+mod media;
+mod mesh;
+mod noise;
+mod pathtracer;
+mod picking;
+mod progressive;
 mod ray;
 mod scene;
+mod scene_file;
+mod sdf;
 mod shapes;
+mod spectral;
+mod stats;
+mod subsurface;
+mod textures;
+mod tonemap;
+mod volume;
 extern crate sdl2;
 
 use std::f32::INFINITY;
@@ -12,32 +36,61 @@ use std::f32::INFINITY;
 use colors::{ROSSO_CORSA, SILVER, SPACE};
 use ray::Ray;
 use sdl2::event::Event;
-use sdl2::gfx::primitives::DrawRenderer;
-use sdl2::keyboard::Keycode;
+use sdl2::event::WindowEvent;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
 use sdl2::pixels;
-use sdl2::render::Canvas;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
 
+use crate::aov::{AovPass, AovSample, Aovs};
 use crate::camera::Camera;
 use crate::colors::{
-    get_vector, BLACK, CARIBBEAN_GREEN, DEEP_PURPLE, MEDIUM_SPRING_GREEN, METALLIC_SEAWEED,
+    get_linear_vector, BLACK, CARIBBEAN_GREEN, DEEP_PURPLE, MEDIUM_SPRING_GREEN, METALLIC_SEAWEED,
     NEON_BLUE, ORANGE_YELLOW, PARADISE_PINK, RUST, WHITE,
 };
-use crate::intersections::nearest_intersected_object;
+use crate::controls::OrbitCamera;
+use crate::denoise::{denoise, DenoiseSettings};
+use crate::exr::ExrImage;
+use crate::framebuffer::{is_float_format, BitDepth, Framebuffer};
+use crate::hud::Hud;
+use crate::intersections::{nearest_intersected_object, visibility, IntersectionRecord};
 use crate::lights::{AmbientLight, LightType, PositionalLight};
+use crate::materials::{Material, ShadingModel};
+use crate::media::{fog_inscattering, fog_transmittance, MAX_FOG_DISTANCE};
+use crate::pathtracer::{ambient_radiance, direct_lighting, trace_path, trace_path_passes};
+use crate::picking::{describe_material, edit_material, highlight, pick, MaterialProperty};
+use crate::progressive::{Frame, ProgressiveRenderer};
 use crate::scene::Scene;
+use crate::scene_file::{load_scene, SceneWatcher};
 use crate::shapes::Sphere;
+use crate::spectral::Ior;
+use crate::stats::{Phase, RayKind, RenderStats};
+use crate::tonemap::{ToneMapper, ToneMapping};
 use nalgebra::Vector3;
 use rand::Rng;
 use rayon::prelude::*;
 use sdl2::pixels::Color;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
 const SCREEN_WIDTH: u32 = 800;
 const SCREEN_HEIGHT: u32 = 600;
 const SAMPLES_PER_PIXEL: u32 = 4;
+const MAX_PATH_DEPTH: u32 = 8;
+// the viewer stops refining after this many passes
+const MAX_PROGRESSIVE_SAMPLES: u32 = 1024;
+// how often the viewer looks for events and new samples
+const FRAME_INTERVAL_MS: u64 = 16;
+// how often the viewer checks whether the scene file changed
+const RELOAD_INTERVAL_MS: u64 = 500;
 
 const REFRACTIVE_INDEX_OF_AMBER: f32 = 1.55;
-const REFRACTIVE_INDEX_OF_DIAMOND: f32 = 2.417;
 
 // colors palette
 
@@ -46,6 +99,14 @@ const BACKGROUND_COLOR: pixels::Color = BLACK;
 #[derive(Debug, Clone)]
 struct DrawSceneError;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RenderMode {
+    Whitted,
+    PathTraced,
+    // path traced one wavelength at a time, shows dispersion
+    Spectral,
+}
+
 fn initialize_scene() -> Scene {
     let mut scene = Scene::default();
 
@@ -54,7 +115,7 @@ fn initialize_scene() -> Scene {
     scene.push(Sphere::new(
         Vector3::new(2.0, 0.0, 0.0),
         0.7,
-        get_vector(CARIBBEAN_GREEN),
+        get_linear_vector(CARIBBEAN_GREEN),
         6100.0,
         0.3,
         REFRACTIVE_INDEX_OF_AMBER,
@@ -63,7 +124,7 @@ fn initialize_scene() -> Scene {
     scene.push(Sphere::new(
         Vector3::new(0.96, 0.36, 0.0),
         0.1,
-        get_vector(PARADISE_PINK),
+        get_linear_vector(PARADISE_PINK),
         70.0,
         0.0,
         REFRACTIVE_INDEX_OF_AMBER,
@@ -72,7 +133,7 @@ fn initialize_scene() -> Scene {
     scene.push(Sphere::new(
         Vector3::new(0.96, 0.85, -0.52),
         0.15,
-        get_vector(DEEP_PURPLE),
+        get_linear_vector(DEEP_PURPLE),
         40.0,
         0.0,
         REFRACTIVE_INDEX_OF_AMBER,
@@ -81,25 +142,28 @@ fn initialize_scene() -> Scene {
     scene.push(Sphere::new(
         Vector3::new(1.2, -0.53, -0.36),
         0.15,
-        get_vector(ROSSO_CORSA),
+        get_linear_vector(ROSSO_CORSA),
         370.0,
         0.5,
         REFRACTIVE_INDEX_OF_AMBER,
     ));
 
-    scene.push(Sphere::new(
+    // amber, light crossing the whole sphere comes out rust colored
+    scene.push(Sphere::with_material(
         Vector3::new(1.2, -0.7, 0.7),
         0.23,
-        get_vector(RUST),
-        570.0,
-        0.2,
-        REFRACTIVE_INDEX_OF_AMBER,
+        Material::dielectric(
+            REFRACTIVE_INDEX_OF_AMBER,
+            0.0,
+            get_linear_vector(RUST),
+            0.46,
+        ),
     ));
 
     scene.push(Sphere::new(
         Vector3::new(1.0, 0.5, 0.6),
         0.2,
-        get_vector(NEON_BLUE),
+        get_linear_vector(NEON_BLUE),
         270.0,
         0.8,
         REFRACTIVE_INDEX_OF_AMBER,
@@ -108,7 +172,7 @@ fn initialize_scene() -> Scene {
     scene.push(Sphere::new(
         Vector3::new(3.0, -0.4, 1.0),
         0.2,
-        get_vector(SPACE),
+        get_linear_vector(SPACE),
         0.0,
         0.0,
         REFRACTIVE_INDEX_OF_AMBER,
@@ -117,19 +181,17 @@ fn initialize_scene() -> Scene {
     scene.push(Sphere::new(
         Vector3::new(2.0, -0.6, 2.0),
         0.05,
-        get_vector(METALLIC_SEAWEED),
+        get_linear_vector(METALLIC_SEAWEED),
         400.0,
         0.0,
         REFRACTIVE_INDEX_OF_AMBER,
     ));
 
-    scene.push(Sphere::new(
+    // yellow diamond, the spectral mode splits the light it refracts
+    scene.push(Sphere::with_material(
         Vector3::new(1.0, 0.05, 0.05),
         0.05,
-        get_vector(ORANGE_YELLOW),
-        6100.0,
-        0.7,
-        REFRACTIVE_INDEX_OF_DIAMOND,
+        Material::dielectric(Ior::diamond(), 0.0, get_linear_vector(ORANGE_YELLOW), 0.1),
     ));
 
     /* lights */
@@ -137,10 +199,10 @@ fn initialize_scene() -> Scene {
     scene.add_light(PositionalLight::new(
         Vector3::new(0.0, -2.0, -2.0),
         0.9,
-        get_vector(WHITE),
+        get_linear_vector(WHITE),
     ));
 
-    scene.add_light(AmbientLight::new(0.4, get_vector(WHITE)));
+    scene.add_light(AmbientLight::new(0.4, get_linear_vector(WHITE)));
 
     scene
 }
@@ -156,9 +218,22 @@ fn refract_ray(
     new_origin: Vector3<f32>,
     refractive_index: f32,
 ) -> Ray {
-    let new_direction = 2.0 * normal * normal.dot(&ray.direction()) - ray.direction();
+    let direction = ray.direction().normalize();
+    // leaving the object when the ray travels along the outward normal
+    let (normal, eta) = if direction.dot(&normal) < 0.0 {
+        (normal, 1.0 / refractive_index)
+    } else {
+        (-normal, refractive_index)
+    };
 
-    return Ray::new(new_origin, new_direction);
+    match bsdf::refract(direction, normal, eta) {
+        Some(new_direction) => Ray::new(new_origin, new_direction),
+        // total internal reflection
+        None => Ray::new(
+            new_origin,
+            direction - 2.0 * normal * normal.dot(&direction),
+        ),
+    }
 }
 
 fn compute_light_intensity(
@@ -175,27 +250,31 @@ fn compute_light_intensity(
             LightType::Ambient => {
                 i += light.intensity();
             }
-            LightType::Positional => {
+            LightType::Positional | LightType::Spot => {
                 let l = light.center() - p;
-                let t_max = 100.0;
+                let intensity = light.intensity()
+                    * light.attenuation(p)
+                    * fog_transmittance(scene, l.norm()).mean();
+                if intensity <= 0.0 {
+                    continue;
+                }
 
-                // is in shadow?
-                let shadow_ray = Ray::new(p, l);
-                let res = nearest_intersected_object(scene, &shadow_ray, 0.001, t_max);
-                if res.is_some() {
+                // partly in shadow behind smoke, fully behind solid objects
+                let intensity = intensity * visibility(scene, &Ray::new(p, l), 0.001, 1.0);
+                if intensity <= 0.0 {
                     continue;
                 }
 
                 let n_dot_l = n.dot(&l);
                 if n_dot_l > 0.0 {
-                    i += light.intensity() * (n_dot_l / (n.norm() * l.norm()));
+                    i += intensity * (n_dot_l / (n.norm() * l.norm()));
                 }
 
                 if s > 0.0 {
                     let r = 2.0 * n * n.dot(&l) - &l;
                     let r_dot_v = r.dot(&v);
                     if r_dot_v > 0.0 {
-                        i += light.intensity() * f32::powf(r_dot_v / (r.norm() * v.norm()), s);
+                        i += intensity * f32::powf(r_dot_v / (r.norm() * v.norm()), s);
                     }
                 }
             }
@@ -205,6 +284,113 @@ fn compute_light_intensity(
     i
 }
 
+fn shade_metallic_roughness(
+    ray: &Ray,
+    res: &IntersectionRecord,
+    scene: &Scene,
+    recursion_depth: i32,
+) -> (Vector3<f32>, Vector3<f32>) {
+    let wo = -ray.direction().normalize();
+    let ambient = ambient_radiance(scene).component_mul(&bsdf::albedo(res));
+    let local_color = (direct_lighting(scene, res, wo) + ambient) * 255.0 + res.object_emission;
+
+    if recursion_depth <= 0 {
+        return (local_color, Vector3::zeros());
+    }
+
+    let reflected_ray = Ray::new(
+        res.intersection_vector,
+        bsdf::reflect(ray.direction(), bsdf::facing_normal(res, wo)),
+    );
+    let reflected_color = trace_secondary(&reflected_ray, scene, recursion_depth - 1);
+
+    (
+        local_color,
+        reflected_color.component_mul(&bsdf::mirror_reflectance(res, wo)),
+    )
+}
+
+fn shade_dielectric(
+    ray: &Ray,
+    res: &IntersectionRecord,
+    scene: &Scene,
+    recursion_depth: i32,
+    ior: Ior,
+    absorption: Vector3<f32>,
+) -> (Vector3<f32>, Vector3<f32>) {
+    let wo = -ray.direction().normalize();
+    let n = bsdf::facing_normal(res, wo);
+    let (eta_i, eta_t) = bsdf::dielectric_etas(res, wo, ior);
+
+    // highlights of the (possibly frosted) surface
+    let mut direct = direct_lighting(scene, res, wo) * 255.0 + res.object_emission;
+    let mut indirect = Vector3::zeros();
+
+    if recursion_depth > 0 {
+        let fresnel = bsdf::fresnel_dielectric(n.dot(&wo), eta_i, eta_t);
+
+        let reflected_ray = Ray::new(res.intersection_vector, bsdf::reflect(-wo, n));
+        indirect += trace_secondary(&reflected_ray, scene, recursion_depth - 1) * fresnel;
+
+        if let Some(direction) = bsdf::refract(-wo, n, eta_i / eta_t) {
+            let refracted_ray = Ray::new(res.intersection_vector, direction);
+            indirect +=
+                trace_secondary(&refracted_ray, scene, recursion_depth - 1) * (1.0 - fresnel);
+        }
+    }
+
+    // the coating sits on the outside
+    if res.geometric_normal.dot(&wo) >= 0.0 {
+        let coating_transmittance = bsdf::coating_transmittance(res, n.dot(&wo));
+        direct = direct.component_mul(&coating_transmittance);
+        indirect = indirect.component_mul(&coating_transmittance)
+            + shade_coat(ray, res, scene, recursion_depth);
+    }
+
+    // hit from inside, the ray traveled through the material to get here
+    if res.geometric_normal.dot(&wo) < 0.0 {
+        let distance = res.intersection_point * ray.direction().norm();
+        let transmittance = bsdf::transmittance(absorption, distance);
+        direct = direct.component_mul(&transmittance);
+        indirect = indirect.component_mul(&transmittance);
+    }
+
+    (direct, indirect)
+}
+
+/// Mirror reflection of a clear coat or thin film, zero for uncoated surfaces.
+fn shade_coat(
+    ray: &Ray,
+    res: &IntersectionRecord,
+    scene: &Scene,
+    recursion_depth: i32,
+) -> Vector3<f32> {
+    let wo = -ray.direction().normalize();
+    let coat = bsdf::coating_reflectance(res, wo);
+    if recursion_depth <= 0 || coat.max() <= 0.0 {
+        return Vector3::zeros();
+    }
+
+    let reflected_ray = Ray::new(
+        res.intersection_vector,
+        bsdf::reflect(ray.direction(), bsdf::facing_normal(res, wo)),
+    );
+    trace_secondary(&reflected_ray, scene, recursion_depth - 1).component_mul(&coat)
+}
+
+/// Scattering event inside a volume, lit like a surface but without reflections.
+fn shade_volume(ray: &Ray, res: &IntersectionRecord, scene: &Scene) -> Vector3<f32> {
+    let wo = -ray.direction().normalize();
+    let ambient = ambient_radiance(scene).component_mul(&bsdf::albedo(res));
+    (direct_lighting(scene, res, wo) + ambient) * 255.0 + res.object_emission
+}
+
+/// Traces a reflected or refracted ray, counted as a secondary ray.
+fn trace_secondary(ray: &Ray, scene: &Scene, recursion_depth: i32) -> Vector3<f32> {
+    stats::count_ray(RayKind::Secondary);
+    trace_ray(ray, scene, 0.001, f32::MAX, recursion_depth)
+}
+
 fn trace_ray(
     ray: &Ray,
     scene: &Scene,
@@ -212,187 +398,824 @@ fn trace_ray(
     t_max: f32,
     recursion_depth: i32,
 ) -> Vector3<f32> {
-    let res = nearest_intersected_object(scene, &ray, t_min, t_max);
+    let (direct, indirect) = trace_ray_passes(ray, scene, t_min, t_max, recursion_depth);
+    direct + indirect
+}
+
+/// Like `trace_ray`, with the light of the first hit kept apart from what its reflections and
+/// refractions bring. Light scattered by the fog counts as direct.
+fn trace_ray_passes(
+    ray: &Ray,
+    scene: &Scene,
+    t_min: f32,
+    t_max: f32,
+    recursion_depth: i32,
+) -> (Vector3<f32>, Vector3<f32>) {
+    let res = nearest_intersected_object(scene, ray, t_min, t_max);
+
+    let medium = match scene.medium {
+        Some(medium) => medium,
+        None => return shade_hit_passes(ray, res, scene, recursion_depth),
+    };
+
+    let distance = res
+        .as_ref()
+        .map_or(MAX_FOG_DISTANCE, |res| {
+            res.intersection_point * ray.direction().norm()
+        })
+        .min(MAX_FOG_DISTANCE);
+    let (direct, indirect) = shade_hit_passes(ray, res, scene, recursion_depth);
+    let transmittance = medium.transmittance(distance);
+
+    (
+        direct.component_mul(&transmittance)
+            + fog_inscattering(scene, ray, distance, &medium) * 255.0,
+        indirect.component_mul(&transmittance),
+    )
+}
 
+/// Shading of the hit split into the surface's own light (direct) and the light brought by its
+/// reflected and refracted rays (indirect).
+fn shade_hit_passes(
+    ray: &Ray,
+    res: Option<IntersectionRecord>,
+    scene: &Scene,
+    recursion_depth: i32,
+) -> (Vector3<f32>, Vector3<f32>) {
     match res {
         Some(res) => {
             /* compute lighting/shading for res.object_color */
 
-            let P = res.intersection_vector;
-            let mut N = P - res.object_center; // sphere normal at intersection
-            N = N / N.norm();
+            match res.object_model {
+                ShadingModel::MetallicRoughness { .. } => {
+                    return shade_metallic_roughness(ray, &res, scene, recursion_depth);
+                }
+                ShadingModel::Dielectric {
+                    ior, absorption, ..
+                } => {
+                    return shade_dielectric(ray, &res, scene, recursion_depth, ior, absorption);
+                }
+                ShadingModel::Volume { .. } => {
+                    return (shade_volume(ray, &res, scene), Vector3::zeros())
+                }
+                // approximated by its diffuse look, light can't travel inside without sampling
+                ShadingModel::Phong | ShadingModel::Subsurface { .. } => {}
+            }
 
-            let local_color = get_vector(res.object_color)
-                * compute_light_intensity(P, N, scene, -ray.direction(), res.object_specular);
+            let P = res.intersection_vector;
+            let N = res.normal;
+
+            // a coating dims the surface under it and adds its own reflection
+            let wo = -ray.direction().normalize();
+            let coat_color = shade_coat(ray, &res, scene, recursion_depth);
+            let local_color = (res.object_color
+                * compute_light_intensity(P, N, scene, -ray.direction(), res.object_specular)
+                + res.object_emission)
+                .component_mul(&bsdf::coating_transmittance(
+                    &res,
+                    bsdf::facing_normal(&res, wo).dot(&wo),
+                ));
 
             let reflective = res.object_reflective;
             let refraction_index = res.object_refractive;
             if reflective <= 0.0 || recursion_depth <= 0 {
-                return local_color;
+                return (local_color, coat_color);
             }
 
             let reflected_ray = reflect_ray(&ray, N, P);
 
-            let reflected_color =
-                trace_ray(&reflected_ray, scene, 0.001, f32::MAX, recursion_depth - 1);
+            let reflected_color = trace_secondary(&reflected_ray, scene, recursion_depth - 1);
 
-            let local_reflected = local_color * (1.0 - reflective) + reflected_color * reflective;
+            let direct = local_color * (1.0 - reflective);
+            let reflected = reflected_color * reflective + coat_color;
             if refraction_index == REFRACTIVE_INDEX_OF_AMBER {
-                return local_reflected;
+                return (direct, reflected);
             }
 
-            let refracted_ray = reflect_ray(&ray, -N, P);
-            let refracted_color =
-                trace_ray(&refracted_ray, scene, 0.001, f32::MAX, recursion_depth - 1);
+            let refracted_ray = refract_ray(ray, N, P, refraction_index);
+            let refracted_color = trace_secondary(&refracted_ray, scene, recursion_depth - 1);
 
-            return local_reflected + refracted_color;
+            return (direct, reflected + refracted_color);
         }
-        None => return get_vector(BACKGROUND_COLOR),
+        None => return (get_linear_vector(BACKGROUND_COLOR), Vector3::zeros()),
     }
 }
 
-fn draw_scene(canvas: &mut Canvas<Window>, cam: &Camera, scene: &Scene) -> Result<(), String> {
+/// Radiance of one camera sample (linear, 0-1) and, if asked for, its auxiliary passes.
+fn render_sample(
+    cam: &Camera,
+    scene: &Scene,
+    mode: RenderMode,
+    x: f32,
+    y: f32,
+    with_aovs: bool,
+) -> (Vector3<f32>, Option<AovSample>) {
+    let ray = cam.get_ray(x, y);
+    stats::count_ray(RayKind::Primary);
+    let secondary_before = stats::thread_ray_counts().secondary;
+    let (color, aov) = trace_sample(&ray, scene, mode, with_aovs);
+    stats::count_path(1 + stats::thread_ray_counts().secondary - secondary_before);
+    (color, aov)
+}
+
+fn trace_sample(
+    ray: &Ray,
+    scene: &Scene,
+    mode: RenderMode,
+    with_aovs: bool,
+) -> (Vector3<f32>, Option<AovSample>) {
+    let background = get_linear_vector(BACKGROUND_COLOR);
+
+    if !with_aovs {
+        let color = match mode {
+            RenderMode::Whitted => trace_ray(ray, scene, 0.001, f32::MAX, 2),
+            RenderMode::PathTraced | RenderMode::Spectral => trace_path(
+                ray,
+                scene,
+                background,
+                MAX_PATH_DEPTH,
+                mode == RenderMode::Spectral,
+            ),
+        };
+        return (color / 255.0, None);
+    }
+
+    let (direct, indirect) = match mode {
+        RenderMode::Whitted => trace_ray_passes(ray, scene, 0.001, f32::MAX, 2),
+        RenderMode::PathTraced | RenderMode::Spectral => trace_path_passes(
+            ray,
+            scene,
+            background,
+            MAX_PATH_DEPTH,
+            mode == RenderMode::Spectral,
+        ),
+    };
+
+    let mut aov = stats::uncounted(|| AovSample::primary(scene, ray));
+    aov.direct = direct / 255.0;
+    aov.indirect = indirect / 255.0;
+    ((direct + indirect) / 255.0, Some(aov))
+}
+
+/// Renders the scene into a linear framebuffer, one rayon task per row, together with the
+/// auxiliary passes when `with_aovs` is set.
+fn render_pass(
+    cam: &Camera,
+    scene: &Scene,
+    mode: RenderMode,
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+    with_aovs: bool,
+) -> (Framebuffer, Option<Aovs>) {
     // using nice and fast rayon code used from https://github.com/fralken/ray-tracing-in-one-weekend/blob/master/src/main.rs
     // courtesy of https://github.com/fralken
     // as I don't understand flat maps and rayon very much yet
-    let colors = (0..SCREEN_HEIGHT)
+    let samples = (0..height)
         .into_par_iter()
         .rev()
         .flat_map(|j| {
-            (0..SCREEN_WIDTH)
+            (0..width)
                 .flat_map(|i| {
-                    let color: Vector3<f32> = (0..SAMPLES_PER_PIXEL)
-                        .map(|_| {
-                            let x = (i as f32) / SCREEN_WIDTH as f32;
-                            let y = (j as f32) / SCREEN_HEIGHT as f32;
-
-                            let ray = cam.get_ray(x, y);
-                            trace_ray(&ray, &scene, 0.001, f32::MAX, 2)
-                        })
-                        .sum();
-
-                    color
-                        .iter()
-                        .map(|c| (c / SAMPLES_PER_PIXEL as f32) as u8)
-                        .collect::<Vec<u8>>()
+                    (0..samples_per_pixel).map(move |_| {
+                        let x = (i as f32) / width as f32;
+                        let y = (j as f32) / height as f32;
+                        render_sample(cam, scene, mode, x, y, with_aovs)
+                    })
                 })
-                .collect::<Vec<u8>>()
+                .collect::<Vec<(Vector3<f32>, Option<AovSample>)>>()
         })
-        .collect::<Vec<u8>>();
-
-    for j in 0..SCREEN_HEIGHT {
-        for i in 0..SCREEN_WIDTH {
-            canvas.pixel(
-                i as i16,
-                j as i16,
-                Color::RGB(
-                    colors[(3 * j * SCREEN_WIDTH + i * 3 + 0) as usize],
-                    colors[(3 * j * SCREEN_WIDTH + i * 3 + 1) as usize],
-                    colors[(3 * j * SCREEN_WIDTH + i * 3 + 2) as usize],
-                ),
-            )?;
+        .collect::<Vec<(Vector3<f32>, Option<AovSample>)>>();
+
+    let pixels = samples
+        .chunks(samples_per_pixel as usize)
+        .map(|pixel| {
+            pixel.iter().map(|(color, _)| color).sum::<Vector3<f32>>() / samples_per_pixel as f32
+        })
+        .collect();
+
+    let aovs = if with_aovs {
+        let aov_samples: Vec<AovSample> = samples.iter().filter_map(|(_, aov)| *aov).collect();
+        Some(Aovs::from_samples(
+            width,
+            height,
+            samples_per_pixel as usize,
+            &aov_samples,
+        ))
+    } else {
+        None
+    };
+
+    let image = Framebuffer {
+        width,
+        height,
+        pixels,
+    };
+    (image, aovs)
+}
+
+fn render_image(
+    cam: &Camera,
+    scene: &Scene,
+    mode: RenderMode,
+    with_aovs: bool,
+) -> (Framebuffer, Option<Aovs>) {
+    render_pass(
+        cam,
+        scene,
+        mode,
+        SCREEN_WIDTH,
+        SCREEN_HEIGHT,
+        SAMPLES_PER_PIXEL,
+        with_aovs,
+    )
+}
+
+/// Largest rectangle with the image's aspect ratio centered in the drawable area, which has more
+/// pixels than the window on HiDPI displays.
+fn display_rect(
+    output_width: u32,
+    output_height: u32,
+    image_width: u32,
+    image_height: u32,
+) -> Rect {
+    let scale = f32::min(
+        output_width as f32 / image_width as f32,
+        output_height as f32 / image_height as f32,
+    );
+    let width = ((image_width as f32 * scale) as u32).max(1);
+    let height = ((image_height as f32 * scale) as u32).max(1);
+
+    Rect::new(
+        (output_width.saturating_sub(width) / 2) as i32,
+        (output_height.saturating_sub(height) / 2) as i32,
+        width,
+        height,
+    )
+}
+
+/// Image point (0-1, y up) under the window position `x`, `y`, None outside the image.
+fn window_to_image(canvas: &Canvas<Window>, x: i32, y: i32) -> Result<Option<(f32, f32)>, String> {
+    let (window_width, window_height) = canvas.window().size();
+    let (output_width, output_height) = canvas.output_size()?;
+    let rect = display_rect(output_width, output_height, SCREEN_WIDTH, SCREEN_HEIGHT);
+
+    // mouse positions are in window coordinates, which HiDPI displays scale up
+    let output_x = x as f32 * output_width as f32 / window_width as f32;
+    let output_y = y as f32 * output_height as f32 / window_height as f32;
+    let u = (output_x - rect.x() as f32) / rect.width() as f32;
+    let v = (output_y - rect.y() as f32) / rect.height() as f32;
+
+    if (0.0..1.0).contains(&u) && (0.0..1.0).contains(&v) {
+        Ok(Some((u, 1.0 - v)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Uploads the frame to `texture` in one copy.
+fn draw_scene(
+    texture: &mut Texture,
+    frame: &Frame,
+    view: Option<AovPass>,
+    tone_mapper: &ToneMapper,
+    denoised: bool,
+    selected: Option<usize>,
+) -> Result<(), String> {
+    // the preview comes without auxiliary passes, it is shown as is until they're there
+    let mut image = match (view, &frame.aovs) {
+        (None, Some(aovs)) if denoised => {
+            tone_mapper.apply(&denoise(&frame.image, aovs, &DenoiseSettings::default()))
         }
+        (Some(pass), Some(aovs)) => aovs.visualize(pass),
+        _ => tone_mapper.apply(&frame.image),
+    };
+    if let (Some(selected), Some(aovs)) = (selected, &frame.aovs) {
+        highlight(&mut image, &aovs.object_id, selected);
     }
 
-    Ok(())
+    texture
+        .update(None, &image.to_srgb8(), 3 * image.width as usize)
+        .map_err(|e| e.to_string())
 }
 
-fn render_scene(canvas: &mut Canvas<Window>, look_at_object: i32, camera_movement: &Vector3<f32>) {
-    // initialize scene:
-    println!("Initializing scene...");
-
-    let scene = initialize_scene();
-    let look_from = Vector3::new(-0.5, 0.0, 0.0) + camera_movement;
-    let look_at = scene.get_nth_element_center(look_at_object);
-    let focus_dist = 10.0;
-    let aperture = 0.1;
+/// Stretches the uploaded frame over the window, with the statistics overlay on top if shown.
+fn present_frame(
+    canvas: &mut Canvas<Window>,
+    texture: &Texture,
+    hud: &Hud,
+    hud_lines: &[String],
+) -> Result<(), String> {
+    let query = texture.query();
+    let (output_width, output_height) = canvas.output_size()?;
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.clear();
+    canvas.copy(
+        texture,
+        None,
+        display_rect(output_width, output_height, query.width, query.height),
+    )?;
+
+    if hud.visible {
+        hud.draw(canvas, hud_lines)?;
+    }
+    canvas.present();
+    Ok(())
+}
 
-    let look_at = match look_at {
+/// Where the viewer and file renders start: a bit behind the origin, looking at the first object.
+fn initial_camera(scene: &Scene) -> OrbitCamera {
+    let look_at = match scene.get_nth_element_center(0) {
         Some(look_at) => look_at,
         None => Vector3::new(1.0, 0.0, 0.0),
     };
 
-    let cam = Camera::new(
-        look_from,
-        look_at,
+    OrbitCamera::looking_at(Vector3::new(-0.5, 0.0, 0.0), look_at, 55.0)
+}
+
+fn create_camera(orbit: &OrbitCamera) -> Camera {
+    let focus_dist = 10.0;
+    let aperture = 0.1;
+
+    Camera::new(
+        orbit.eye(),
+        orbit.target,
         Vector3::new(0.0, 1.0, 0.0),
-        55.0,
+        orbit.fov,
         SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
         aperture,
         focus_dist,
+    )
+}
+
+/// Starts refining the view from scratch in the background, e.g. after the camera moved.
+fn render_scene(
+    renderer: &ProgressiveRenderer,
+    scene: &Arc<RwLock<Scene>>,
+    orbit: &OrbitCamera,
+    mode: RenderMode,
+) {
+    let cam = create_camera(orbit);
+    let scene = Arc::clone(scene);
+    // Whitted rays are deterministic, more passes wouldn't change the image
+    let max_samples = match mode {
+        RenderMode::Whitted => 1,
+        RenderMode::PathTraced | RenderMode::Spectral => MAX_PROGRESSIVE_SAMPLES,
+    };
+
+    renderer.restart(
+        Arc::new(move |width, height, with_aovs| {
+            // edits wait for the pass in flight to finish
+            let scene = scene.read().unwrap();
+            render_pass(&cam, &scene, mode, width, height, 1, with_aovs)
+        }),
+        max_samples,
     );
+}
 
-    println!("Drawing scene");
-    draw_scene(canvas, &cam, &scene);
-    println!("Scene drawed");
-    canvas.present();
+/// The scene given with `--scene` and the files it depends on, or the built-in scene.
+fn load_scene_argument(args: &[String]) -> Result<(Scene, Vec<PathBuf>), String> {
+    match argument_value(args, "--scene") {
+        Some(path) => {
+            let scene_file = load_scene(path)?;
+            Ok((scene_file.scene, scene_file.dependencies))
+        }
+        None => Ok((initialize_scene(), Vec::new())),
+    }
+}
+
+/// Value following `flag` on the command line, e.g. `--output render.png`.
+fn argument_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|index| args.get(index + 1))
+        .map(|value| value.as_str())
+}
+
+/// Renders a single frame without opening a window and writes it to `output`.
+fn render_to_file(args: &[String], output: &str) -> Result<(), String> {
+    let mode = match argument_value(args, "--mode") {
+        None | Some("whitted") => RenderMode::Whitted,
+        Some("path") => RenderMode::PathTraced,
+        Some("spectral") => RenderMode::Spectral,
+        Some(mode) => return Err(format!("unknown render mode: {}", mode)),
+    };
+    let bit_depth = if args.iter().any(|arg| arg == "--16bit") {
+        BitDepth::Sixteen
+    } else {
+        BitDepth::Eight
+    };
+    let tone_mapper = ToneMapper::new(
+        ToneMapping::from_name(argument_value(args, "--tonemap").unwrap_or("clamp"))?,
+        match argument_value(args, "--exposure") {
+            Some(exposure) => exposure
+                .parse()
+                .map_err(|_| format!("invalid exposure: {}", exposure))?,
+            None => 0.0,
+        },
+    );
+    let with_aovs = args.iter().any(|arg| arg == "--aovs");
+    if with_aovs && !output.to_lowercase().ends_with(".exr") {
+        return Err("auxiliary passes can only be written to an .exr file".to_string());
+    }
+
+    let (scene, _) = stats::time_phase(Phase::SceneLoad, || load_scene_argument(args))?;
+    let cam = create_camera(&initial_camera(&scene));
+
+    println!("Rendering to {}", output);
+    let denoised = args.iter().any(|arg| arg == "--denoise");
+    let (image, aovs) = stats::time_phase(Phase::Render, || {
+        render_image(&cam, &scene, mode, with_aovs || denoised)
+    });
+    let image = match &aovs {
+        Some(aovs) if denoised => stats::time_phase(Phase::Denoise, || {
+            denoise(&image, aovs, &DenoiseSettings::default())
+        }),
+        _ => image,
+    };
+
+    stats::time_phase(Phase::Save, || match aovs {
+        Some(aovs) if with_aovs => {
+            let mut exr = ExrImage::new(image.width, image.height);
+            exr.add_rgb_layer("", &image)?;
+            aovs.add_to_exr(&mut exr)?;
+            exr.save(output)
+        }
+        // float formats keep the scene's linear values, tone mapping is for display
+        _ if is_float_format(output) => image.save(output, bit_depth),
+        _ => tone_mapper.apply(&image).save(output, bit_depth),
+    })?;
+
+    let render_stats = RenderStats::collect();
+    print!("{}", render_stats.summary());
+    if let Some(path) = argument_value(args, "--stats") {
+        fs::write(path, render_stats.to_json()).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(output) = argument_value(&args, "--output") {
+        return render_to_file(&args, output);
+    }
+
     let sdl_context = sdl2::init()?;
     let video_subsys = sdl_context.video()?;
     let window = video_subsys
         .window("Raytracer by Wiktor Flis", SCREEN_WIDTH, SCREEN_HEIGHT)
         .position_centered()
         .opengl()
+        .resizable()
+        .allow_highdpi()
         .build()
         .map_err(|e| e.to_string())?;
 
     let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH, SCREEN_HEIGHT)
+        .map_err(|e| e.to_string())?;
+    println!("Initializing scene...");
+    let (scene, dependencies) = load_scene_argument(&args)?;
+    let scene = Arc::new(RwLock::new(scene));
+    // a scene file is reloaded when it or anything it references changes, the camera stays
+    let mut watcher = SceneWatcher::new(&dependencies);
+    let mut last_reload_check = Instant::now();
+    let renderer = ProgressiveRenderer::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let mut frame: Option<Frame> = None;
+    let mut seen_version = 0;
+    // left drag orbits (looks around in fly mode), right drag pans, the wheel dollies or with
+    // Ctrl zooms, F toggles fly mode where WASD/QE move the eye
+    let mut orbit = initial_camera(&scene.read().unwrap());
     let mut look_at_object = 0;
-    let mut camera_movement = Vector3::new(0.0, 0.0, 0.0);
     let rate_of_camera_movement = 0.3;
+    let mut render_mode = RenderMode::Whitted;
+    // auxiliary pass shown instead of the image, cycled with V
+    let mut view: Option<AovPass> = None;
+    // T cycles the operator, +/- change the exposure by half a stop
+    let mut tone_mapper = ToneMapper::default();
+    // N toggles the denoiser
+    let mut denoised = false;
 
-    render_scene(&mut canvas, look_at_object, &camera_movement);
+    render_scene(&renderer, &scene, &orbit, render_mode);
 
     let mut events = sdl_context.event_pump()?;
 
     let mut lastx = 0;
     let mut lasty = 0;
+    // set once the mouse moves with a button held, so releasing it doesn't pick
+    let mut dragged = false;
+    let mut selected: Option<usize> = None;
+    // H shows frame time, ray rates and camera parameters
+    let mut hud = Hud::new();
 
     'main: loop {
+        let mut camera_changed = false;
+        let mut display_changed = false;
+
         for event in events.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'main,
 
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => display_changed = true,
+
+                Event::MouseButtonDown { x, y, .. } => {
+                    lastx = x;
+                    lasty = y;
+                    dragged = false;
+                }
+
+                // a left click that didn't drag selects the object under the mouse
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } if !dragged => {
+                    selected = match window_to_image(&canvas, x, y)? {
+                        Some((u, v)) => pick(&scene.read().unwrap(), &create_camera(&orbit), u, v),
+                        None => None,
+                    };
+                    match selected {
+                        Some(index) => println!("Selected object {}", index),
+                        None => println!("Selection cleared"),
+                    }
+                    display_changed = true;
+                }
+
+                Event::MouseMotion {
+                    mousestate, x, y, ..
+                } => {
+                    let (dx, dy) = ((x - lastx) as f32, (y - lasty) as f32);
+                    lastx = x;
+                    lasty = y;
+
+                    if mousestate.left() || mousestate.right() || mousestate.middle() {
+                        dragged = dragged || dx != 0.0 || dy != 0.0;
+                    }
+                    if mousestate.left() {
+                        orbit.rotate(dx, dy);
+                        camera_changed = true;
+                    } else if mousestate.right() || mousestate.middle() {
+                        orbit.pan(dx, dy);
+                        camera_changed = true;
+                    }
+                }
+
+                Event::MouseWheel { y, .. } => {
+                    let modifiers = sdl_context.keyboard().mod_state();
+                    if modifiers.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+                        orbit.zoom(y as f32);
+                    } else {
+                        orbit.dolly(y as f32);
+                    }
+                    camera_changed = true;
+                }
+
                 Event::KeyDown {
                     keycode: Some(keycode),
+                    keymod,
                     ..
                 } => {
                     if keycode == Keycode::Escape {
                         break 'main;
                     }
 
-                    if keycode == Keycode::W {
-                        camera_movement += Vector3::new(0.0, 1.0, 0.0) * rate_of_camera_movement;
-                        render_scene(&mut canvas, look_at_object, &camera_movement);
+                    // screen relative moves, in fly mode W and S go along the view instead
+                    let (forward, right, up) = match keycode {
+                        Keycode::W if orbit.fly => (1.0, 0.0, 0.0),
+                        Keycode::S if orbit.fly => (-1.0, 0.0, 0.0),
+                        Keycode::W => (0.0, 0.0, 1.0),
+                        Keycode::S => (0.0, 0.0, -1.0),
+                        Keycode::A => (0.0, -1.0, 0.0),
+                        Keycode::D => (0.0, 1.0, 0.0),
+                        Keycode::E if orbit.fly => (0.0, 0.0, 1.0),
+                        Keycode::Q if orbit.fly => (0.0, 0.0, -1.0),
+                        _ => (0.0, 0.0, 0.0),
+                    };
+                    if forward != 0.0 || right != 0.0 || up != 0.0 {
+                        orbit.translate(
+                            forward * rate_of_camera_movement,
+                            right * rate_of_camera_movement,
+                            up * rate_of_camera_movement,
+                        );
+                        camera_changed = true;
+                    }
+
+                    if keycode == Keycode::F {
+                        orbit.fly = !orbit.fly;
+                        println!("Fly mode {}", if orbit.fly { "on" } else { "off" });
+                    }
+
+                    if keycode == Keycode::P {
+                        render_mode = match render_mode {
+                            RenderMode::Whitted => RenderMode::PathTraced,
+                            RenderMode::PathTraced => RenderMode::Spectral,
+                            RenderMode::Spectral => RenderMode::Whitted,
+                        };
+                        camera_changed = true;
                     }
 
-                    if keycode == Keycode::S {
-                        camera_movement += Vector3::new(0.0, 1.0, 0.0) * rate_of_camera_movement;
-                        render_scene(&mut canvas, look_at_object, &camera_movement);
+                    if keycode == Keycode::V {
+                        view = match view {
+                            None => Some(AovPass::ALL[0]),
+                            Some(pass) => {
+                                let index = AovPass::ALL.iter().position(|&p| p == pass).unwrap();
+                                AovPass::ALL.get(index + 1).cloned()
+                            }
+                        };
+                        println!("Showing {}", view.map_or("image", |pass| pass.name()));
+                        display_changed = true;
                     }
 
-                    if keycode == Keycode::A {
-                        camera_movement += Vector3::new(0.0, 0.0, -1.0) * rate_of_camera_movement;
-                        render_scene(&mut canvas, look_at_object, &camera_movement);
+                    if keycode == Keycode::T
+                        || keycode == Keycode::Equals
+                        || keycode == Keycode::KpPlus
+                        || keycode == Keycode::Minus
+                        || keycode == Keycode::KpMinus
+                    {
+                        match keycode {
+                            Keycode::T => tone_mapper.operator = tone_mapper.operator.next(),
+                            Keycode::Equals | Keycode::KpPlus => tone_mapper.exposure += 0.5,
+                            _ => tone_mapper.exposure -= 0.5,
+                        }
+                        println!(
+                            "Tone mapping: {}, exposure {:+.1} EV",
+                            tone_mapper.operator.name(),
+                            tone_mapper.exposure
+                        );
+                        display_changed = true;
                     }
 
-                    if keycode == Keycode::D {
-                        camera_movement += Vector3::new(0.0, 0.0, 1.0) * rate_of_camera_movement;
-                        render_scene(&mut canvas, look_at_object, &camera_movement);
+                    if keycode == Keycode::N {
+                        denoised = !denoised;
+                        println!("Denoiser {}", if denoised { "on" } else { "off" });
+                        display_changed = true;
+                    }
+
+                    // 1-6 raise color, specular, reflective and refractive values, Shift lowers
+                    let property = match keycode {
+                        Keycode::Num1 => Some(MaterialProperty::Red),
+                        Keycode::Num2 => Some(MaterialProperty::Green),
+                        Keycode::Num3 => Some(MaterialProperty::Blue),
+                        Keycode::Num4 => Some(MaterialProperty::Specular),
+                        Keycode::Num5 => Some(MaterialProperty::Reflective),
+                        Keycode::Num6 => Some(MaterialProperty::Refractive),
+                        _ => None,
+                    };
+                    if let (Some(property), Some(index)) = (property, selected) {
+                        let direction = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            -1.0
+                        } else {
+                            1.0
+                        };
+
+                        let mut scene = scene.write().unwrap();
+                        let object = &mut scene.objects[index];
+                        let center = object.center();
+                        let material = object.material_mut();
+                        edit_material(material, property, direction, center);
+                        println!("Object {}: {}", index, describe_material(material, center));
+                        camera_changed = true;
+                    }
+
+                    if keycode == Keycode::H {
+                        hud.visible = !hud.visible;
+                        display_changed = true;
                     }
 
                     if keycode == Keycode::Space {
-                        look_at_object += 1;
-                        render_scene(&mut canvas, look_at_object, &camera_movement);
+                        let scene = scene.read().unwrap();
+                        look_at_object = (look_at_object + 1) % scene.objects.len().max(1) as i32;
+                        if let Some(center) = scene.get_nth_element_center(look_at_object) {
+                            orbit.target = center;
+                        }
+                        camera_changed = true;
                     }
                 }
 
                 _ => {}
             }
         }
+
+        if let Some(path) = argument_value(&args, "--scene") {
+            if last_reload_check.elapsed() >= Duration::from_millis(RELOAD_INTERVAL_MS) {
+                last_reload_check = Instant::now();
+                if watcher.changed() {
+                    match load_scene(path) {
+                        Ok(scene_file) => {
+                            println!("Reloaded {}", path);
+                            *scene.write().unwrap() = scene_file.scene;
+                            watcher = SceneWatcher::new(&scene_file.dependencies);
+                            selected = None;
+                            camera_changed = true;
+                        }
+                        Err(e) => println!("Keeping the previous scene: {}", e),
+                    }
+                }
+            }
+        }
+
+        if camera_changed {
+            render_scene(&renderer, &scene, &orbit, render_mode);
+        }
+
+        if let Some(latest) = renderer.latest(&mut seen_version) {
+            frame = Some(latest);
+            display_changed = true;
+        }
+
+        let mut redraw = false;
+        if let (true, Some(frame)) = (display_changed, &frame) {
+            draw_scene(&mut texture, frame, view, &tone_mapper, denoised, selected)?;
+            canvas
+                .window_mut()
+                .set_title(&format!(
+                    "Raytracer by Wiktor Flis - {} samples",
+                    frame.samples
+                ))
+                .map_err(|e| e.to_string())?;
+            redraw = true;
+        }
+        if hud.update() && hud.visible {
+            redraw = true;
+        }
+
+        if let (true, Some(frame)) = (redraw, &frame) {
+            let hud_lines = hud.lines(frame, &orbit, &format!("{:?}", render_mode));
+            present_frame(&mut canvas, &texture, &hud, &hud_lines)?;
+        }
+
+        thread::sleep(Duration::from_millis(FRAME_INTERVAL_MS));
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whitted_coating_reflects() {
+        // a black sphere in front of the camera, a glowing one behind it
+        let trace = |coated: bool| {
+            let mut material = Material::new(Vector3::zeros(), 0.0, 0.0, 1.0);
+            if coated {
+                material = material.with_clear_coat(1.0, 0.0);
+            }
+            let mut scene = Scene::default();
+            scene.push(Sphere::with_material(
+                Vector3::new(3.0, 0.0, 0.0),
+                1.0,
+                material,
+            ));
+            scene.push(Sphere::with_material(
+                Vector3::new(-3.0, 0.0, 0.0),
+                1.0,
+                Material::new(Vector3::zeros(), 0.0, 0.0, 1.0)
+                    .with_emission(Vector3::repeat(255.0), 1.0),
+            ));
+
+            let ray = Ray::new(Vector3::zeros(), Vector3::new(1.0, 0.0, 0.0));
+            trace_ray(&ray, &scene, 0.001, f32::MAX, 2)
+        };
+
+        assert_eq!(trace(false), Vector3::zeros());
+        // about 4% of the glow at normal incidence
+        assert!((trace(true) - Vector3::repeat(0.04 * 255.0)).norm() < 1.0);
+    }
+
+    #[test]
+    fn test_whitted_indirect_pass_is_not_negative() {
+        // a bright mirror-ish sphere reflecting the dark background
+        let mut scene = Scene::default();
+        scene.push(Sphere::new(
+            Vector3::new(3.0, 0.0, 0.0),
+            1.0,
+            Vector3::repeat(200.0),
+            50.0,
+            0.5,
+            REFRACTIVE_INDEX_OF_AMBER,
+        ));
+        scene.add_light(PositionalLight::new(
+            Vector3::new(0.0, 2.0, 0.0),
+            0.8,
+            Vector3::repeat(255.0),
+        ));
+        scene.add_light(AmbientLight::new(0.2, Vector3::repeat(255.0)));
+
+        for i in 0..9 {
+            let y = (i as f32 - 4.0) / 15.0;
+            let ray = Ray::new(Vector3::zeros(), Vector3::new(1.0, y, 0.1));
+            let (direct, indirect) = trace_ray_passes(&ray, &scene, 0.001, f32::MAX, 2);
+
+            assert!(indirect.min() >= 0.0, "indirect {:?} at {}", indirect, y);
+            assert!(direct.min() > 0.0);
+        }
+    }
+}
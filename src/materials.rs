@@ -0,0 +1,231 @@
+use crate::bsdf::luminance;
+use crate::layers::{ClearCoat, Coating, ThinFilm};
+use crate::spectral::Ior;
+use crate::textures::{SolidColor, Texture};
+use nalgebra::{Vector2, Vector3};
+use std::sync::Arc;
+
+/// Fine surface detail applied to the shading normal only.
+#[derive(Debug, Clone)]
+pub enum NormalMap {
+    /// Tangent-space normal map, 0-255 colors encode components in [-1, 1] (OpenGL convention, +y along v).
+    TangentSpace(Arc<dyn Texture>),
+    /// Height map, the normal tilts along the gradient of the texture luminance.
+    Bump {
+        height: Arc<dyn Texture>,
+        strength: f32,
+    },
+}
+
+/// How the renderer turns a material into reflected light.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadingModel {
+    /// Classic Phong lobe driven by `specular`, with `reflective` mirror reflection.
+    Phong,
+    /// glTF 2.0 metallic/roughness model, base color comes from `color`.
+    /// GGX distribution, Smith masking-shadowing and Schlick Fresnel.
+    MetallicRoughness { metallic: f32, roughness: f32 },
+    /// Glass-like surface, smooth or frosted (GGX microfacet BTDF) depending on `roughness`.
+    /// Light traveling inside is attenuated by `absorption` per unit distance (Beer-Lambert).
+    Dielectric {
+        ior: Ior,
+        roughness: f32,
+        absorption: Vector3<f32>,
+    },
+    /// Translucent material like skin, wax or marble: light refracts through a smooth boundary
+    /// and random walks inside, `color` is the overall albedo and `mean_free_path` the average
+    /// distance between scattering events per channel.
+    Subsurface { mean_free_path: Vector3<f32> },
+    /// Scattering event inside a participating medium, `color` is the single-scattering albedo
+    /// and `g` the Henyey-Greenstein asymmetry of the phase function.
+    Volume { g: f32 },
+}
+
+// step in uv used to differentiate bump maps
+const BUMP_DELTA: f32 = 1e-3;
+
+/// Surface properties shared by all shapes.
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub color: Arc<dyn Texture>,
+    pub specular: f32,
+    pub reflective: f32,
+    pub refractive: f32,
+    pub normal_map: Option<NormalMap>,
+    pub model: ShadingModel,
+    // emitted radiance, same 0-255 scale as colors
+    pub emission: Vector3<f32>,
+    pub coating: Coating,
+}
+
+impl Material {
+    pub fn new(color: Vector3<f32>, specular: f32, reflective: f32, refractive: f32) -> Self {
+        Material::textured(
+            Arc::new(SolidColor::new(color)),
+            specular,
+            reflective,
+            refractive,
+        )
+    }
+
+    pub fn textured(
+        color: Arc<dyn Texture>,
+        specular: f32,
+        reflective: f32,
+        refractive: f32,
+    ) -> Self {
+        Material {
+            color,
+            specular,
+            reflective,
+            refractive,
+            normal_map: None,
+            model: ShadingModel::Phong,
+            emission: Vector3::zeros(),
+            coating: Coating::default(),
+        }
+    }
+
+    /// Transparent material tinted by its thickness. The index of refraction can vary with
+    /// wavelength (see `Ior`), which shows as dispersion in spectral renders. Light that travels `tint_distance` units
+    /// inside it keeps the `tint` color (0-255), thinner parts are lighter and thicker darker.
+    pub fn dielectric(
+        ior: impl Into<Ior>,
+        roughness: f32,
+        tint: Vector3<f32>,
+        tint_distance: f32,
+    ) -> Self {
+        let ior = ior.into();
+        let absorption = tint.map(|c| -(c / 255.0).max(1e-4).ln() / tint_distance);
+
+        Material {
+            model: ShadingModel::Dielectric {
+                ior,
+                roughness: roughness.clamp(0.0, 1.0),
+                absorption,
+            },
+            ..Material::new(Vector3::new(255.0, 255.0, 255.0), 0.0, 0.0, ior.nominal())
+        }
+    }
+
+    /// Subsurface scattering material, `color` (0-255) is the albedo seen from afar and
+    /// `mean_free_path` how far light travels inside, per channel. Rendered as a diffuse surface
+    /// by the Whitted tracer.
+    pub fn subsurface(color: Vector3<f32>, mean_free_path: Vector3<f32>) -> Self {
+        Material {
+            model: ShadingModel::Subsurface { mean_free_path },
+            ..Material::new(color, 0.0, 0.0, 1.0)
+        }
+    }
+
+    /// Makes the surface glow, the path tracer also samples it as a light source.
+    pub fn with_emission(mut self, color: Vector3<f32>, strength: f32) -> Self {
+        self.emission = color * strength;
+        self
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.emission.max() > 0.0
+    }
+
+    /// Physically based material with glTF semantics, so values exported by content tools
+    /// can be used as is.
+    pub fn metallic_roughness(base_color: Arc<dyn Texture>, metallic: f32, roughness: f32) -> Self {
+        Material {
+            model: ShadingModel::MetallicRoughness {
+                metallic: metallic.clamp(0.0, 1.0),
+                roughness: roughness.clamp(0.0, 1.0),
+            },
+            ..Material::textured(base_color, 0.0, 0.0, 1.0)
+        }
+    }
+
+    /// Adds a varnish layer with its own glossy reflection over the material.
+    pub fn with_clear_coat(mut self, strength: f32, roughness: f32) -> Self {
+        self.coating.clear_coat = Some(ClearCoat {
+            strength,
+            roughness,
+        });
+        self
+    }
+
+    /// Adds an iridescent film, `thickness` in nanometers (a few hundred for soap and oil).
+    pub fn with_thin_film(mut self, thickness: f32, ior: f32) -> Self {
+        self.coating.thin_film = Some(ThinFilm { thickness, ior });
+        self
+    }
+
+    pub fn with_normal_map(mut self, normal_map: NormalMap) -> Self {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
+    pub fn color_at(&self, uv: Vector2<f32>, p: Vector3<f32>) -> Vector3<f32> {
+        self.color.value(uv, p)
+    }
+
+    /// Shading normal at the hit point given the geometric normal and the shape's tangent frame
+    /// (`tangent` along increasing u, `bitangent` along increasing v).
+    pub fn shading_normal(
+        &self,
+        uv: Vector2<f32>,
+        p: Vector3<f32>,
+        normal: Vector3<f32>,
+        tangent: Vector3<f32>,
+        bitangent: Vector3<f32>,
+    ) -> Vector3<f32> {
+        match &self.normal_map {
+            None => normal,
+            Some(NormalMap::TangentSpace(texture)) => {
+                let n = texture.value(uv, p) / 127.5 - Vector3::new(1.0, 1.0, 1.0);
+                (tangent * n.x + bitangent * n.y + normal * n.z).normalize()
+            }
+            Some(NormalMap::Bump { height, strength }) => {
+                // step both uv and position so image and solid (noise) height maps work
+                let h = |uv: Vector2<f32>, p: Vector3<f32>| luminance(height.value(uv, p)) / 255.0;
+                let h0 = h(uv, p);
+                let dh_du = (h(uv + Vector2::new(BUMP_DELTA, 0.0), p + tangent * BUMP_DELTA) - h0)
+                    / BUMP_DELTA;
+                let dh_dv = (h(
+                    uv + Vector2::new(0.0, BUMP_DELTA),
+                    p + bitangent * BUMP_DELTA,
+                ) - h0)
+                    / BUMP_DELTA;
+
+                (normal - *strength * (dh_du * tangent + dh_dv * bitangent)).normalize()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intersections::tangent_frame;
+
+    #[test]
+    fn test_flat_maps_keep_the_geometric_normal() {
+        let normal = Vector3::new(1.0, 2.0, -0.5).normalize();
+        let (tangent, bitangent) = tangent_frame(normal, Vector3::new(1.0, 0.0, 0.0));
+        assert!(tangent.dot(&normal).abs() < 1e-6);
+        assert!((bitangent - tangent.cross(&normal)).norm() < 1e-6);
+        assert!((bitangent.norm() - 1.0).abs() < 1e-6);
+
+        let plain = Material::new(Vector3::repeat(255.0), 0.0, 0.0, 1.0);
+        let flat_normals = plain
+            .clone()
+            .with_normal_map(NormalMap::TangentSpace(Arc::new(SolidColor::new(
+                Vector3::new(127.5, 127.5, 255.0),
+            ))));
+        let flat_heights = plain.clone().with_normal_map(NormalMap::Bump {
+            height: Arc::new(SolidColor::new(Vector3::repeat(100.0))),
+            strength: 4.0,
+        });
+
+        let (uv, p) = (Vector2::new(0.3, 0.7), Vector3::new(0.1, 0.2, 0.3));
+        for material in [plain, flat_normals, flat_heights] {
+            let shading_normal = material.shading_normal(uv, p, normal, tangent, bitangent);
+            assert!((shading_normal - normal).norm() < 1e-5);
+        }
+    }
+}
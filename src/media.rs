@@ -0,0 +1,297 @@
+use crate::intersections::{tangent_frame, visibility, Intersectable, IntersectionRecord};
+use crate::layers::Coating;
+use crate::lights::LightType;
+use crate::materials::{Material, ShadingModel};
+use crate::ray::Ray;
+use crate::scene::Scene;
+use nalgebra::Vector3;
+use rand::Rng;
+use std::f32::consts::PI;
+
+// scene-wide fog ends this far from the ray origin, so the sky stays visible through it
+pub const MAX_FOG_DISTANCE: f32 = 100.0;
+// ray marching steps used by the Whitted tracer to gather light scattered by fog
+const FOG_MARCHING_STEPS: u32 = 16;
+
+/// Homogeneous participating medium, coefficients are per unit distance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Medium {
+    pub absorption: Vector3<f32>,
+    pub scattering: Vector3<f32>,
+    /// Henyey-Greenstein asymmetry, > 0 scatters forward, < 0 backward.
+    pub g: f32,
+}
+
+impl Medium {
+    pub fn new(absorption: Vector3<f32>, scattering: Vector3<f32>, g: f32) -> Self {
+        Medium {
+            absorption,
+            scattering,
+            g,
+        }
+    }
+
+    /// Gray, mostly scattering medium, `density` is the extinction per unit distance.
+    pub fn fog(density: f32, g: f32) -> Self {
+        Medium::new(
+            Vector3::repeat(0.1 * density),
+            Vector3::repeat(0.9 * density),
+            g,
+        )
+    }
+
+    pub fn extinction(&self) -> Vector3<f32> {
+        self.absorption + self.scattering
+    }
+
+    /// Extinction used to sample distances, exact for gray media.
+    pub fn mean_extinction(&self) -> f32 {
+        self.extinction().mean()
+    }
+
+    pub fn albedo(&self) -> Vector3<f32> {
+        self.scattering
+            .zip_map(&self.extinction(), |s, t| if t > 0.0 { s / t } else { 0.0 })
+    }
+
+    pub fn transmittance(&self, distance: f32) -> Vector3<f32> {
+        self.extinction().map(|sigma| f32::exp(-sigma * distance))
+    }
+
+    /// Free-flight distance to the next interaction, infinite in an empty medium.
+    pub fn sample_distance(&self, rng: &mut impl Rng) -> f32 {
+        let sigma = self.mean_extinction();
+        if sigma <= 0.0 {
+            return f32::INFINITY;
+        }
+        -(1.0 - rng.gen::<f32>()).ln() / sigma
+    }
+
+    /// Weight of a free-flight sample that scattered at `distance`, corrects for sampling with
+    /// the mean extinction instead of the per-channel one.
+    pub fn scatter_weight(&self, distance: f32) -> Vector3<f32> {
+        let sigma = self.mean_extinction();
+        self.transmittance(distance)
+            .component_mul(&self.extinction())
+            / (sigma * f32::exp(-sigma * distance))
+    }
+
+    /// Weight of a free-flight sample that passed `distance` without interacting.
+    pub fn pass_weight(&self, distance: f32) -> Vector3<f32> {
+        self.transmittance(distance) / f32::exp(-self.mean_extinction() * distance)
+    }
+}
+
+/// Henyey-Greenstein phase function, `cos_theta` between propagation and scattered direction.
+pub fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.max(1e-8).sqrt())
+}
+
+/// Scattered direction for light propagating along `direction`, distributed by the phase function.
+pub fn sample_henyey_greenstein(
+    direction: Vector3<f32>,
+    g: f32,
+    rng: &mut impl Rng,
+) -> Vector3<f32> {
+    let u1: f32 = rng.gen();
+    let u2: f32 = rng.gen();
+
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u1
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+        (1.0 + g * g - s * s) / (2.0 * g)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+
+    let (tangent, bitangent) = tangent_frame(direction, Vector3::new(1.0, 0.0, 0.0));
+    (tangent * sin_theta * phi.cos() + bitangent * sin_theta * phi.sin() + direction * cos_theta)
+        .normalize()
+}
+
+/// Hit record for a scattering event at `p`, shaded with the volume phase function.
+pub fn scatter_record(
+    ray: &Ray,
+    t: f32,
+    medium: &Medium,
+    object_center: Vector3<f32>,
+) -> IntersectionRecord {
+    let p = ray.point_at_parameter(t);
+    // volumes have no surface, face the normal towards the viewer so shading code can use it
+    let normal = -ray.direction().normalize();
+
+    IntersectionRecord {
+        intersection_point: t,
+        intersection_vector: p,
+        object_center,
+        object_index: 0,
+        normal,
+        geometric_normal: normal,
+        object_color: medium.albedo() * 255.0,
+        object_specular: 0.0,
+        object_reflective: 0.0,
+        object_refractive: 1.0,
+        object_model: ShadingModel::Volume { g: medium.g },
+        object_emission: Vector3::zeros(),
+        object_coating: Coating::default(),
+    }
+}
+
+/// Smoke, fog or colored liquid filling the inside of another shape.
+///
+/// Intersections are stochastic: a ray entering the boundary either scatters somewhere inside
+/// or passes through, with the probability given by the medium's transmittance.
+pub struct ConstantMedium {
+    pub boundary: Box<dyn Intersectable>,
+    pub medium: Medium,
+    material: Material,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: impl Intersectable + 'static, medium: Medium) -> Self {
+        let mut material = Material::new(medium.albedo() * 255.0, 0.0, 0.0, 1.0);
+        material.model = ShadingModel::Volume { g: medium.g };
+
+        ConstantMedium {
+            boundary: Box::new(boundary),
+            medium,
+            material,
+        }
+    }
+}
+
+impl Intersectable for ConstantMedium {
+    fn center(&self) -> Vector3<f32> {
+        self.boundary.center()
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<IntersectionRecord> {
+        let (t_enter, t_exit) = boundary_interval(self.boundary.as_ref(), ray, t_min, t_max)?;
+        let direction_length = ray.direction().norm();
+        let distance_inside = (t_exit - t_enter) * direction_length;
+        let hit_distance = self.medium.sample_distance(&mut rand::thread_rng());
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / direction_length;
+        Some(scatter_record(ray, t, &self.medium, self.center()))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        match boundary_interval(self.boundary.as_ref(), ray, t_min, t_max) {
+            Some((t_enter, t_exit)) => {
+                let distance = (t_exit - t_enter) * ray.direction().norm();
+                f32::exp(-self.medium.mean_extinction() * distance)
+            }
+            None => 1.0,
+        }
+    }
+}
+
+/// Part of `ray` between `t_min` and `t_max` lying inside a closed `boundary` shape.
+pub fn boundary_interval(
+    boundary: &dyn Intersectable,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32)> {
+    // entry may lie behind the origin when the ray starts inside the volume
+    let entry = boundary.intersect(ray, f32::MIN, f32::MAX)?;
+    let exit = boundary.intersect(ray, entry.intersection_point + 1e-4, f32::MAX)?;
+
+    let t_enter = entry.intersection_point.max(t_min);
+    let t_exit = exit.intersection_point.min(t_max);
+    if t_enter >= t_exit {
+        return None;
+    }
+
+    Some((t_enter, t_exit))
+}
+
+/// Attenuation of light traveling `distance` through the scene fog, one without fog.
+pub fn fog_transmittance(scene: &Scene, distance: f32) -> Vector3<f32> {
+    match scene.medium {
+        Some(medium) => medium.transmittance(distance.min(MAX_FOG_DISTANCE)),
+        None => Vector3::new(1.0, 1.0, 1.0),
+    }
+}
+
+/// Light scattered towards the ray origin by the scene fog along the first `distance` units of
+/// `ray`, in the 0-1 range. Ray marched with jittered steps, shadow rays make the light shafts.
+pub fn fog_inscattering(scene: &Scene, ray: &Ray, distance: f32, medium: &Medium) -> Vector3<f32> {
+    let mut rng = rand::thread_rng();
+    let direction = ray.direction().normalize();
+    let step = distance / FOG_MARCHING_STEPS as f32;
+    let ambient: Vector3<f32> = scene
+        .lights
+        .iter()
+        .filter(|light| matches!(light.light_type(), LightType::Ambient))
+        .map(|light| light.color() / 255.0 * light.intensity())
+        .sum();
+
+    let mut radiance = Vector3::zeros();
+
+    for i in 0..FOG_MARCHING_STEPS {
+        let s = (i as f32 + rng.gen::<f32>()) * step;
+        let p = ray.origin() + direction * s;
+        // ambient light is isotropic, the phase function integrates to one
+        let mut inscattered = ambient;
+
+        for light in scene.lights.iter() {
+            if !matches!(light.light_type(), LightType::Positional | LightType::Spot) {
+                continue;
+            }
+
+            let attenuation = light.attenuation(p);
+            if attenuation <= 0.0 {
+                continue;
+            }
+
+            let l = light.center() - p;
+            let visible = visibility(scene, &Ray::new(p, l), 1e-3, 1.0);
+            if visible <= 0.0 {
+                continue;
+            }
+
+            let phase = henyey_greenstein(direction.dot(&l.normalize()), medium.g);
+            let irradiance = light.color() / 255.0 * light.intensity() * attenuation * visible * PI;
+            inscattered += irradiance.component_mul(&fog_transmittance(scene, l.norm())) * phase;
+        }
+
+        radiance += inscattered
+            .component_mul(&medium.scattering)
+            .component_mul(&medium.transmittance(s))
+            * step;
+    }
+
+    radiance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_henyey_greenstein_is_normalized() {
+        for g in [-0.5, 0.0, 0.3, 0.8] {
+            let steps = 20000;
+            let integral: f32 = (0..steps)
+                .map(|i| -1.0 + 2.0 * (i as f32 + 0.5) / steps as f32)
+                .map(|cos_theta| henyey_greenstein(cos_theta, g) * 2.0 * PI * 2.0 / steps as f32)
+                .sum();
+
+            assert!((integral - 1.0).abs() < 1e-2);
+        }
+    }
+}
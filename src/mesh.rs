@@ -0,0 +1,225 @@
+use crate::intersections::{tangent_frame, Intersectable, IntersectionRecord};
+use crate::materials::Material;
+use crate::ray::Ray;
+use nalgebra::{Vector2, Vector3};
+use rand::{Rng, RngCore};
+use std::fs;
+
+/// Indexed triangle mesh with flat shading, tested triangle by triangle.
+pub struct TriangleMesh {
+    pub vertices: Vec<Vector3<f32>>,
+    pub triangles: Vec<[usize; 3]>,
+    pub material: Material,
+    // running sum of triangle areas, used to pick triangles proportionally to their size
+    cumulative_areas: Vec<f32>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        vertices: Vec<Vector3<f32>>,
+        triangles: Vec<[usize; 3]>,
+        material: Material,
+    ) -> Self {
+        let cumulative_areas = triangles
+            .iter()
+            .scan(0.0, |total, triangle| {
+                let [a, b, c] = triangle.map(|i| vertices[i]);
+                *total += 0.5 * (b - a).cross(&(c - a)).norm();
+                Some(*total)
+            })
+            .collect();
+
+        TriangleMesh {
+            vertices,
+            triangles,
+            material,
+            cumulative_areas,
+        }
+    }
+
+    /// Loads the vertices and faces of a Wavefront OBJ file, polygons are split into fans.
+    /// Normals, texture coordinates and groups are ignored.
+    pub fn from_obj(path: &str, material: Material) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut vertices = Vec::new();
+        let mut triangles = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: &str| format!("{}:{}: {}", path, number + 1, message);
+            let mut tokens = line.split_whitespace();
+
+            match tokens.next() {
+                Some("v") => {
+                    let coordinates: Vec<f32> = tokens
+                        .take(3)
+                        .map(|token| token.parse().map_err(|_| error("invalid vertex")))
+                        .collect::<Result<_, _>>()?;
+                    if coordinates.len() != 3 {
+                        return Err(error("vertex needs three coordinates"));
+                    }
+                    vertices.push(Vector3::new(coordinates[0], coordinates[1], coordinates[2]));
+                }
+                Some("f") => {
+                    // "f 1 2 3" or "f 1/1/1 2/2/2 3/3/3", negative indices count from the end
+                    let indices: Vec<usize> = tokens
+                        .map(|token| {
+                            let index: i64 = token
+                                .split('/')
+                                .next()
+                                .unwrap_or("")
+                                .parse()
+                                .map_err(|_| error("invalid face index"))?;
+                            let index = if index < 0 {
+                                vertices.len() as i64 + index
+                            } else {
+                                index - 1
+                            };
+                            if index < 0 || index >= vertices.len() as i64 {
+                                return Err(error("face index out of range"));
+                            }
+                            Ok(index as usize)
+                        })
+                        .collect::<Result<_, _>>()?;
+                    if indices.len() < 3 {
+                        return Err(error("face needs at least three vertices"));
+                    }
+                    for i in 1..indices.len() - 1 {
+                        triangles.push([indices[0], indices[i], indices[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(TriangleMesh::new(vertices, triangles, material))
+    }
+
+    /// Parallelogram spanned by `edge_u` and `edge_v` from `corner`, facing edge_u x edge_v.
+    pub fn quad(
+        corner: Vector3<f32>,
+        edge_u: Vector3<f32>,
+        edge_v: Vector3<f32>,
+        material: Material,
+    ) -> Self {
+        TriangleMesh::new(
+            vec![
+                corner,
+                corner + edge_u,
+                corner + edge_u + edge_v,
+                corner + edge_v,
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+            material,
+        )
+    }
+}
+
+impl Intersectable for TriangleMesh {
+    fn center(&self) -> Vector3<f32> {
+        self.vertices.iter().sum::<Vector3<f32>>() / self.vertices.len().max(1) as f32
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn area(&self) -> f32 {
+        self.cumulative_areas.last().cloned().unwrap_or(0.0)
+    }
+
+    fn sample_surface(&self, rng: &mut dyn RngCore) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let target = rng.gen::<f32>() * self.area();
+        let index = self
+            .cumulative_areas
+            .partition_point(|&area| area < target)
+            .min(self.triangles.len().checked_sub(1)?);
+        let [a, b, c] = self.triangles[index].map(|i| self.vertices[i]);
+
+        // uniform point on the triangle by folding the unit square
+        let (mut u, mut v): (f32, f32) = (rng.gen(), rng.gen());
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+
+        let normal = (b - a).cross(&(c - a)).normalize();
+        Some((a + u * (b - a) + v * (c - a), normal))
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<IntersectionRecord> {
+        let mut nearest: Option<(f32, f32, f32, usize)> = None;
+        let mut nearest_t = t_max;
+
+        for (index, triangle) in self.triangles.iter().enumerate() {
+            let [a, b, c] = triangle.map(|i| self.vertices[i]);
+            if let Some((t, u, v)) = intersect_triangle(ray, a, b, c) {
+                if t_min < t && t < nearest_t {
+                    nearest_t = t;
+                    nearest = Some((t, u, v, index));
+                }
+            }
+        }
+
+        let (t, u, v, index) = nearest?;
+        let [a, b, c] = self.triangles[index].map(|i| self.vertices[i]);
+        let p = ray.point_at_parameter(t);
+        let local = p - self.center();
+        let normal = (b - a).cross(&(c - a)).normalize();
+        let uv = Vector2::new(u, v);
+        let (tangent, bitangent) = tangent_frame(normal, b - a);
+
+        Some(IntersectionRecord {
+            intersection_point: t,
+            intersection_vector: p,
+            object_center: self.center(),
+            object_index: 0,
+            normal: self
+                .material
+                .shading_normal(uv, local, normal, tangent, bitangent),
+            geometric_normal: normal,
+            object_color: self.material.color_at(uv, local),
+            object_specular: self.material.specular,
+            object_reflective: self.material.reflective,
+            object_refractive: self.material.refractive,
+            object_model: self.material.model,
+            object_emission: self.material.emission,
+            object_coating: self.material.coating,
+        })
+    }
+}
+
+/// Möller–Trumbore ray/triangle test, returns ray parameter and barycentrics of b and c.
+pub fn intersect_triangle(
+    ray: &Ray,
+    a: Vector3<f32>,
+    b: Vector3<f32>,
+    c: Vector3<f32>,
+) -> Option<(f32, f32, f32)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = ray.direction().cross(&edge2);
+    let determinant = edge1.dot(&p);
+
+    if determinant.abs() < 1e-9 {
+        return None;
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let s = ray.origin() - a;
+    let u = s.dot(&p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(&edge1);
+    let v = ray.direction().dot(&q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    Some((edge2.dot(&q) * inverse_determinant, u, v))
+}
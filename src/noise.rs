@@ -0,0 +1,324 @@
+use crate::textures::Texture;
+use nalgebra::{Vector2, Vector3};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+#[derive(Debug, Clone, Copy)]
+pub enum NoiseBasis {
+    Perlin,
+    Simplex,
+}
+
+/// Gradient noise generator, values are roughly in [-1, 1].
+///
+/// The same seed always produces the same pattern, so renders stay reproducible.
+#[derive(Debug, Clone)]
+pub struct Noise {
+    permutation: Vec<usize>,
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut table: Vec<usize> = (0..256).collect();
+        table.shuffle(&mut rng);
+
+        // doubled so lookups of hash + 1 never need wrapping
+        let permutation = table.iter().chain(table.iter()).cloned().collect();
+        Noise { permutation }
+    }
+
+    fn hash(&self, x: i32, y: i32, z: i32) -> usize {
+        let p = &self.permutation;
+        p[p[p[(x & 255) as usize] + (y & 255) as usize] + (z & 255) as usize]
+    }
+
+    pub fn sample(&self, basis: NoiseBasis, p: Vector3<f32>) -> f32 {
+        match basis {
+            NoiseBasis::Perlin => self.perlin(p),
+            NoiseBasis::Simplex => self.simplex(p),
+        }
+    }
+
+    /// Improved Perlin noise (Perlin 2002).
+    pub fn perlin(&self, p: Vector3<f32>) -> f32 {
+        let cell = p.map(|c| c.floor());
+        let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+        let f = p - cell;
+        let fade = f.map(|t| t * t * t * (t * (t * 6.0 - 15.0) + 10.0));
+
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            let offset = Vector3::new(dx as f32, dy as f32, dz as f32);
+            perlin_gradient(self.hash(x + dx, y + dy, z + dz), f - offset)
+        };
+
+        let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), fade.x);
+        let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), fade.x);
+        let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), fade.x);
+        let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), fade.x);
+
+        lerp(lerp(x00, x10, fade.y), lerp(x01, x11, fade.y), fade.z)
+    }
+
+    /// 3D simplex noise (Gustavson's reference implementation).
+    pub fn simplex(&self, p: Vector3<f32>) -> f32 {
+        const F3: f32 = 1.0 / 3.0;
+        const G3: f32 = 1.0 / 6.0;
+
+        // skew into simplex cell space
+        let s = (p.x + p.y + p.z) * F3;
+        let cell = (p + Vector3::repeat(s)).map(|c| c.floor());
+        let t = (cell.x + cell.y + cell.z) * G3;
+        let x0 = p - (cell - Vector3::repeat(t));
+
+        // which of the six tetrahedra the point is in
+        let (o1, o2) = if x0.x >= x0.y {
+            if x0.y >= x0.z {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0.x >= x0.z {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if x0.y < x0.z {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0.x < x0.z {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+
+        let (i, j, k) = (cell.x as i32, cell.y as i32, cell.z as i32);
+        let corners = [(0, 0, 0), o1, o2, (1, 1, 1)];
+
+        let mut n = 0.0;
+        for (c, &(di, dj, dk)) in corners.iter().enumerate() {
+            let offset =
+                Vector3::new(di as f32, dj as f32, dk as f32) - Vector3::repeat(c as f32 * G3);
+            let d = x0 - offset;
+            let falloff = 0.6 - d.norm_squared();
+            if falloff > 0.0 {
+                let g = perlin_gradient(self.hash(i + di, j + dj, k + dk), d);
+                n += falloff.powi(4) * g;
+            }
+        }
+
+        32.0 * n
+    }
+
+    /// Fractional Brownian motion, `octaves` layers of noise at doubling frequency.
+    pub fn fbm(&self, basis: NoiseBasis, p: Vector3<f32>, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut amplitude = 0.5;
+        let mut frequency = 1.0;
+
+        for _ in 0..octaves {
+            sum += amplitude * self.sample(basis, p * frequency);
+            frequency *= 2.0;
+            amplitude *= 0.5;
+        }
+
+        sum
+    }
+
+    /// Like `fbm` but summing absolute values, gives the billowy look of smoke and veins.
+    pub fn turbulence(&self, basis: NoiseBasis, p: Vector3<f32>, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut amplitude = 0.5;
+        let mut frequency = 1.0;
+
+        for _ in 0..octaves {
+            sum += amplitude * self.sample(basis, p * frequency).abs();
+            frequency *= 2.0;
+            amplitude *= 0.5;
+        }
+
+        sum
+    }
+}
+
+/// Dot product with one of the 12 cube-edge gradient directions.
+fn perlin_gradient(hash: usize, d: Vector3<f32>) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { d.x } else { d.y };
+    let v = if h < 4 {
+        d.y
+    } else if h == 12 || h == 14 {
+        d.x
+    } else {
+        d.z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum NoisePattern {
+    Plain,
+    Fbm { octaves: u32 },
+    Turbulence { octaves: u32 },
+}
+
+/// Blends two colors by a noise value sampled at `scale` times the object-space hit point.
+#[derive(Debug, Clone)]
+pub struct NoiseTexture {
+    pub noise: Noise,
+    pub basis: NoiseBasis,
+    pub pattern: NoisePattern,
+    pub scale: f32,
+    pub low: Vector3<f32>,
+    pub high: Vector3<f32>,
+}
+
+impl NoiseTexture {
+    pub fn new(
+        basis: NoiseBasis,
+        pattern: NoisePattern,
+        scale: f32,
+        low: Vector3<f32>,
+        high: Vector3<f32>,
+    ) -> Self {
+        NoiseTexture {
+            noise: Noise::new(0),
+            basis,
+            pattern,
+            scale,
+            low,
+            high,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _uv: Vector2<f32>, p: Vector3<f32>) -> Vector3<f32> {
+        let p = p * self.scale;
+        let t = match self.pattern {
+            NoisePattern::Plain => 0.5 * (1.0 + self.noise.sample(self.basis, p)),
+            NoisePattern::Fbm { octaves } => 0.5 * (1.0 + self.noise.fbm(self.basis, p, octaves)),
+            NoisePattern::Turbulence { octaves } => self.noise.turbulence(self.basis, p, octaves),
+        };
+
+        self.low.lerp(&self.high, t.clamp(0.0, 1.0))
+    }
+}
+
+/// Sine bands along x distorted by turbulence.
+#[derive(Debug, Clone)]
+pub struct MarbleTexture {
+    pub noise: Noise,
+    pub scale: f32,
+    pub distortion: f32,
+    pub base: Vector3<f32>,
+    pub vein: Vector3<f32>,
+}
+
+impl MarbleTexture {
+    pub fn new(scale: f32, distortion: f32, base: Vector3<f32>, vein: Vector3<f32>) -> Self {
+        MarbleTexture {
+            noise: Noise::new(1),
+            scale,
+            distortion,
+            base,
+            vein,
+        }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _uv: Vector2<f32>, p: Vector3<f32>) -> Vector3<f32> {
+        let p = p * self.scale;
+        let turbulence = self.noise.turbulence(NoiseBasis::Perlin, p, 7);
+        let t = 0.5 * (1.0 + f32::sin(p.x + self.distortion * turbulence));
+
+        self.vein.lerp(&self.base, t.powf(0.5))
+    }
+}
+
+/// Concentric rings around the object's y axis with noise-perturbed spacing.
+#[derive(Debug, Clone)]
+pub struct WoodTexture {
+    pub noise: Noise,
+    pub ring_frequency: f32,
+    pub distortion: f32,
+    pub light: Vector3<f32>,
+    pub dark: Vector3<f32>,
+}
+
+impl WoodTexture {
+    pub fn new(
+        ring_frequency: f32,
+        distortion: f32,
+        light: Vector3<f32>,
+        dark: Vector3<f32>,
+    ) -> Self {
+        WoodTexture {
+            noise: Noise::new(2),
+            ring_frequency,
+            distortion,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _uv: Vector2<f32>, p: Vector3<f32>) -> Vector3<f32> {
+        let radius = Vector2::new(p.x, p.z).norm() * self.ring_frequency;
+        let grain = self.distortion * self.noise.fbm(NoiseBasis::Perlin, p * 4.0, 4);
+        // the grain can push the radius below zero near the axis, fract would go negative
+        let rings = (radius + grain).rem_euclid(1.0);
+
+        // sharp dark edge at the end of each ring, like latewood
+        self.light.lerp(&self.dark, rings.powi(3))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perlin_vanishes_on_lattice() {
+        let noise = Noise::new(42);
+        assert_eq!(noise.perlin(Vector3::new(3.0, -2.0, 7.0)), 0.0);
+    }
+
+    #[test]
+    fn test_noise_is_bounded_and_seeded() {
+        let a = Noise::new(7);
+        let b = Noise::new(7);
+
+        for i in 0..1000 {
+            let p = Vector3::new(i as f32 * 0.137, i as f32 * 0.071, i as f32 * -0.053);
+            assert!(a.perlin(p).abs() <= 1.1);
+            assert!(a.simplex(p).abs() <= 1.1);
+            assert_eq!(a.simplex(p), b.simplex(p));
+        }
+    }
+
+    #[test]
+    fn test_wood_stays_between_its_colors_near_the_axis() {
+        let light = Vector3::new(200.0, 150.0, 100.0);
+        let dark = Vector3::new(50.0, 30.0, 10.0);
+        let wood = WoodTexture::new(8.0, 2.0, light, dark);
+
+        for i in 0..200 {
+            let t = i as f32 * 0.1;
+            let p = Vector3::new(0.01 * t.sin(), t - 10.0, 0.01 * t.cos());
+            let color = wood.value(Vector2::zeros(), p);
+            for c in 0..3 {
+                assert!(
+                    color[c] <= light[c] && color[c] >= dark[c],
+                    "{:?} at {:?}",
+                    color,
+                    p
+                );
+            }
+        }
+    }
+}
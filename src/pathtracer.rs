@@ -0,0 +1,399 @@
+use crate::bsdf;
+use crate::intersections::{nearest_intersected_object, visibility, IntersectionRecord};
+use crate::lights::LightType;
+use crate::materials::ShadingModel;
+use crate::media::{self, fog_transmittance, MAX_FOG_DISTANCE};
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::spectral;
+use crate::stats::{self, RayKind};
+use crate::subsurface;
+use nalgebra::Vector3;
+use rand::{Rng, RngCore};
+use std::f32::consts::PI;
+
+const RAY_EPSILON: f32 = 1e-3;
+// paths are terminated at random after this many bounces
+const RUSSIAN_ROULETTE_DEPTH: u32 = 3;
+
+/// Light from positional and spot lights reaching `res` and leaving towards `wo`, in the 0-1
+/// range, attenuated by the scene fog on the way.
+///
+/// Positional lights deliver an irradiance of `intensity * PI` at normal incidence, so a white
+/// diffuse surface comes out as bright as under the Phong model.
+pub fn direct_lighting(scene: &Scene, res: &IntersectionRecord, wo: Vector3<f32>) -> Vector3<f32> {
+    let p = res.intersection_vector;
+    let mut radiance = Vector3::zeros();
+
+    for light in scene.lights.iter() {
+        if let LightType::Positional | LightType::Spot = light.light_type() {
+            let attenuation = light.attenuation(p);
+            let l = light.center() - p;
+            let wi = l.normalize();
+            let n_dot_l = bsdf::cos_factor(res, wo, wi);
+            if n_dot_l <= 0.0 || attenuation <= 0.0 {
+                continue;
+            }
+
+            // the light sits at t = 1.0 along the unnormalized shadow ray
+            let visible = visibility(scene, &Ray::new(p, l), RAY_EPSILON, 1.0);
+            if visible <= 0.0 {
+                continue;
+            }
+
+            let irradiance =
+                (light.color() / 255.0 * light.intensity() * attenuation * visible * PI)
+                    .component_mul(&fog_transmittance(scene, l.norm()));
+            radiance += bsdf::eval(res, wo, wi).component_mul(&irradiance) * n_dot_l;
+        }
+    }
+
+    radiance
+}
+
+/// Solid angle density of reaching `res` (an emitter hit) from `origin` by emitter sampling.
+fn emitter_pdf(
+    scene: &Scene,
+    emitter_count: usize,
+    res: &IntersectionRecord,
+    origin: Vector3<f32>,
+) -> f32 {
+    let area = scene.objects[res.object_index].area();
+    if area <= 0.0 {
+        return 0.0;
+    }
+
+    let to_light = res.intersection_vector - origin;
+    let distance_squared = to_light.norm_squared();
+    let cos_light = res.geometric_normal.dot(&to_light.normalize()).abs();
+    if cos_light <= 0.0 {
+        return 0.0;
+    }
+
+    distance_squared / (cos_light * area * emitter_count as f32)
+}
+
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b <= 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+/// Light from one randomly chosen emissive object, weighted against BSDF sampling.
+fn sample_emitters(
+    scene: &Scene,
+    emitters: &[usize],
+    res: &IntersectionRecord,
+    wo: Vector3<f32>,
+    rng: &mut dyn RngCore,
+) -> Vector3<f32> {
+    if emitters.is_empty() {
+        return Vector3::zeros();
+    }
+
+    let light = &scene.objects[emitters[rng.gen_range(0..emitters.len())]];
+    let (q, light_normal) = match light.sample_surface(rng) {
+        Some(sample) => sample,
+        None => return Vector3::zeros(),
+    };
+
+    let p = res.intersection_vector;
+    let to_light = q - p;
+    let distance_squared = to_light.norm_squared();
+    let wi = to_light / distance_squared.sqrt();
+
+    // emission is one-sided, towards the outward normal
+    let cos_light = -light_normal.dot(&wi);
+    let n_dot_l = bsdf::cos_factor(res, wo, wi);
+    if cos_light <= 0.0 || n_dot_l <= 0.0 {
+        return Vector3::zeros();
+    }
+
+    let visible = visibility(
+        scene,
+        &Ray::new(p, to_light),
+        RAY_EPSILON,
+        1.0 - RAY_EPSILON,
+    );
+    if visible <= 0.0 {
+        return Vector3::zeros();
+    }
+
+    let light_pdf = distance_squared / (cos_light * light.area() * emitters.len() as f32);
+    let weight = power_heuristic(light_pdf, bsdf::pdf(res, wo, wi));
+    let emission = (light.material().emission / 255.0)
+        .component_mul(&fog_transmittance(scene, distance_squared.sqrt()));
+
+    bsdf::eval(res, wo, wi).component_mul(&emission) * n_dot_l * visible * weight / light_pdf
+}
+
+/// Uniform radiance of the ambient lights, acts as a constant sky for escaped paths.
+pub fn ambient_radiance(scene: &Scene) -> Vector3<f32> {
+    scene
+        .lights
+        .iter()
+        .filter(|light| matches!(light.light_type(), LightType::Ambient))
+        .map(|light| light.color() / 255.0 * light.intensity())
+        .sum()
+}
+
+/// Unidirectional path tracer with next event estimation towards positional lights and
+/// emissive objects, the latter combined with BSDF sampling by multiple importance sampling.
+///
+/// Returns radiance in the same 0-255 range as `trace_ray`, `background` is used for camera
+/// rays that miss the scene. Scene fog is handled by free-flight sampling: a path segment either
+/// scatters in the fog or reaches the next surface. With `spectral` set the path carries a single
+/// random wavelength, so dispersive materials split light into colors.
+pub fn trace_path(
+    ray: &Ray,
+    scene: &Scene,
+    background: Vector3<f32>,
+    max_depth: u32,
+    spectral: bool,
+) -> Vector3<f32> {
+    let (direct, indirect) = trace_path_passes(ray, scene, background, max_depth, spectral);
+    direct + indirect
+}
+
+/// `trace_path` split into direct light, reaching the first hit straight from the lights and
+/// the sky (or the camera from emitters and the background), and the indirect rest.
+pub fn trace_path_passes(
+    ray: &Ray,
+    scene: &Scene,
+    background: Vector3<f32>,
+    max_depth: u32,
+    spectral: bool,
+) -> (Vector3<f32>, Vector3<f32>) {
+    let mut rng = rand::thread_rng();
+    let wavelength = if spectral {
+        Some(spectral::sample_wavelength(&mut rng))
+    } else {
+        None
+    };
+    let mut ray = Ray::new(ray.origin(), ray.direction().normalize());
+    let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+    let mut radiance = Vector3::zeros();
+    // radiance gathered before the first bounce's own lighting, the direct pass
+    let mut direct: Option<Vector3<f32>> = None;
+    let emitters = scene.emitters();
+    // pdf of the last bounce, None for camera rays and mirror bounces which can't be light sampled
+    let mut bounce_pdf: Option<f32> = None;
+    // where light leaves a subsurface material, shaded next instead of intersecting the scene
+    let mut subsurface_exit: Option<IntersectionRecord> = None;
+
+    for depth in 0..max_depth {
+        let exited_subsurface = subsurface_exit.is_some();
+        let mut hit = match subsurface_exit.take() {
+            Some(exit) => Some(exit),
+            None => {
+                // the camera ray was counted by the caller
+                if depth > 0 {
+                    stats::count_ray(RayKind::Secondary);
+                }
+                nearest_intersected_object(scene, &ray, RAY_EPSILON, f32::MAX)
+            }
+        };
+
+        // the walk inside a subsurface material doesn't cross the fog
+        if let Some(medium) = scene.medium.filter(|_| !exited_subsurface) {
+            // rays are normalized here, so the ray parameter is a distance
+            let hit_distance = hit
+                .as_ref()
+                .map_or(MAX_FOG_DISTANCE, |res| res.intersection_point)
+                .min(MAX_FOG_DISTANCE);
+            let scatter_distance = medium.sample_distance(&mut rng);
+
+            if scatter_distance < hit_distance {
+                throughput = throughput.component_mul(&medium.scatter_weight(scatter_distance));
+                hit = Some(media::scatter_record(
+                    &ray,
+                    scatter_distance,
+                    &medium,
+                    ray.origin(),
+                ));
+            } else {
+                throughput = throughput.component_mul(&medium.pass_weight(hit_distance));
+            }
+        }
+
+        let mut res = match hit {
+            Some(res) => res,
+            None => {
+                let sky = if depth == 0 {
+                    background / 255.0
+                } else {
+                    ambient_radiance(scene)
+                };
+                radiance += throughput.component_mul(&sky);
+                break;
+            }
+        };
+
+        let wo = -ray.direction();
+
+        if let Some(wavelength) = wavelength {
+            spectral::to_monochromatic(&mut res, wavelength);
+        }
+
+        if let ShadingModel::Dielectric { absorption, .. } = res.object_model {
+            // hitting the inside of a dielectric means the last segment traveled through it
+            if res.geometric_normal.dot(&wo) < 0.0 {
+                let transmittance = bsdf::transmittance(absorption, res.intersection_point);
+                throughput = throughput.component_mul(&transmittance);
+            }
+        }
+
+        if res.object_emission.max() > 0.0 && res.geometric_normal.dot(&wo) > 0.0 {
+            let weight = match bounce_pdf {
+                Some(pdf) => {
+                    power_heuristic(pdf, emitter_pdf(scene, emitters.len(), &res, ray.origin()))
+                }
+                None => 1.0,
+            };
+            radiance += throughput.component_mul(&(res.object_emission / 255.0)) * weight;
+        }
+
+        if depth == 1 {
+            direct = Some(radiance);
+        }
+
+        radiance += throughput.component_mul(&direct_lighting(scene, &res, wo));
+        radiance += throughput.component_mul(&sample_emitters(scene, emitters, &res, wo, &mut rng));
+
+        let sample = match bsdf::sample(&res, wo, &mut rng) {
+            Some(sample) => sample,
+            None => break,
+        };
+        throughput = throughput.component_mul(&sample.weight);
+        bounce_pdf = if sample.specular {
+            None
+        } else {
+            Some(sample.pdf)
+        };
+
+        if let ShadingModel::Subsurface { mean_free_path } = res.object_model {
+            // refracted into the object, walk through it to where the light comes out
+            if sample.direction.dot(&res.geometric_normal) < 0.0 {
+                let (exit, weight) = match subsurface::random_walk(
+                    scene,
+                    &res,
+                    sample.direction,
+                    mean_free_path,
+                    &mut rng,
+                ) {
+                    Some(walk) => walk,
+                    None => break,
+                };
+
+                throughput = throughput.component_mul(&weight);
+                // arrive from inside along the normal, so the exit is seen from outside
+                ray = Ray::new(exit.intersection_vector, -exit.normal);
+                subsurface_exit = Some(exit);
+                continue;
+            }
+        }
+
+        if depth >= RUSSIAN_ROULETTE_DEPTH {
+            let survival = throughput.max().min(0.95);
+            if rng.gen::<f32>() >= survival {
+                break;
+            }
+            throughput /= survival;
+        }
+
+        ray = Ray::new(res.intersection_vector, sample.direction);
+    }
+
+    let direct = direct.unwrap_or(radiance);
+    let to_rgb = |radiance: Vector3<f32>| match wavelength {
+        Some(wavelength) => spectral::wavelength_to_rgb(radiance, wavelength) * 255.0,
+        None => radiance * 255.0,
+    };
+
+    (to_rgb(direct), to_rgb(radiance - direct))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::Material;
+    use crate::mesh::TriangleMesh;
+    use std::f32::consts::FRAC_PI_3;
+
+    #[test]
+    fn test_power_heuristic_weights_sum_to_one() {
+        for (a, b) in [(0.3, 2.0), (5.0, 0.1), (1.0, 1.0), (0.0, 4.0)] {
+            assert!((power_heuristic(a, b) + power_heuristic(b, a) - 1.0).abs() < 1e-6);
+        }
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_emitter_pdf_integrates_to_the_solid_angle() {
+        // 2x2 square at distance 1 in front of the origin, facing it
+        let mut scene = Scene::default();
+        scene.push(TriangleMesh::quad(
+            Vector3::new(-1.0, -1.0, 1.0),
+            Vector3::new(0.0, 2.0, 0.0),
+            Vector3::new(2.0, 0.0, 0.0),
+            Material::new(Vector3::zeros(), 0.0, 0.0, 1.0)
+                .with_emission(Vector3::repeat(255.0), 1.0),
+        ));
+        let light = &scene.objects[0];
+        let origin = Vector3::zeros();
+        let mut rng = rand::thread_rng();
+
+        // the mean of 1 / pdf over points drawn by the light sampling is the solid angle
+        let samples = 20000;
+        let mut solid_angle = 0.0;
+        for _ in 0..samples {
+            let (q, _) = light.sample_surface(&mut rng).unwrap();
+            let res = nearest_intersected_object(&scene, &Ray::new(origin, q), 0.0, 2.0).unwrap();
+            solid_angle += 1.0 / emitter_pdf(&scene, 1, &res, origin);
+        }
+        solid_angle /= samples as f32;
+
+        let expected = 4.0 * (4.0 / 8.0f32).asin();
+        assert!((expected - 2.0 * FRAC_PI_3).abs() < 1e-5);
+        assert!((solid_angle - expected).abs() < 0.02 * expected);
+    }
+
+    #[test]
+    fn test_area_light_with_mis_matches_the_form_factor() {
+        // gray floor lit only by a 2x2 emitter one unit above the shaded point
+        let mut scene = Scene::default();
+        scene.push(TriangleMesh::quad(
+            Vector3::new(-50.0, 0.0, -50.0),
+            Vector3::new(0.0, 0.0, 100.0),
+            Vector3::new(100.0, 0.0, 0.0),
+            Material::new(Vector3::repeat(127.5), 0.0, 0.0, 1.0),
+        ));
+        scene.push(TriangleMesh::quad(
+            Vector3::new(-1.0, 1.0, -1.0),
+            Vector3::new(2.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 2.0),
+            Material::new(Vector3::zeros(), 0.0, 0.0, 1.0)
+                .with_emission(Vector3::repeat(255.0), 1.0),
+        ));
+        assert_eq!(scene.emitters(), &[1]);
+
+        let camera_ray = Ray::new(Vector3::new(0.0, 0.5, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        let samples = 20000;
+        // two segments: the emitter is reached by light sampling and by the bounce
+        let radiance = (0..samples)
+            .map(|_| trace_path(&camera_ray, &scene, Vector3::zeros(), 2, false).x)
+            .sum::<f32>()
+            / samples as f32;
+
+        // form factor of the square from below its center, four corner rectangles of 1x1 at
+        // height 1, times the albedo and the emitted radiance
+        let x = 1.0f32;
+        let s = (1.0 + x * x).sqrt();
+        let form_factor = 4.0 * (2.0 * x / s * (x / s).atan()) / (2.0 * PI);
+        let expected = 0.5 * form_factor * 255.0;
+        assert!((radiance - expected).abs() < 0.03 * expected);
+    }
+}
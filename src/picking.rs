@@ -0,0 +1,99 @@
+use crate::camera::Camera;
+use crate::framebuffer::Framebuffer;
+use crate::intersections::nearest_intersected_object;
+use crate::materials::Material;
+use crate::scene::Scene;
+use crate::textures::SolidColor;
+use nalgebra::{Vector2, Vector3};
+use std::sync::Arc;
+
+// outline drawn around the selected object
+const HIGHLIGHT_COLOR: [f32; 3] = [1.0, 0.8, 0.0];
+// color channels use the 0-255 scale of the materials
+const COLOR_STEP: f32 = 16.0;
+const SPECULAR_FACTOR: f32 = 1.5;
+const REFLECTIVE_STEP: f32 = 0.05;
+const REFRACTIVE_STEP: f32 = 0.05;
+
+/// Material value adjusted by the viewer's editing keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaterialProperty {
+    Red,
+    Green,
+    Blue,
+    Specular,
+    Reflective,
+    Refractive,
+}
+
+/// Index in `Scene::objects` of the object seen through the image point `x`, `y` (0-1, y up).
+pub fn pick(scene: &Scene, cam: &Camera, x: f32, y: f32) -> Option<usize> {
+    let ray = cam.get_ray(x, y);
+    nearest_intersected_object(scene, &ray, 0.001, f32::MAX).map(|res| res.object_index)
+}
+
+/// Steps `property` up (`direction` 1) or down (-1). Editing the color replaces a texture by
+/// its value at `p`.
+pub fn edit_material(
+    material: &mut Material,
+    property: MaterialProperty,
+    direction: f32,
+    p: Vector3<f32>,
+) {
+    match property {
+        MaterialProperty::Red | MaterialProperty::Green | MaterialProperty::Blue => {
+            let channel = match property {
+                MaterialProperty::Red => 0,
+                MaterialProperty::Green => 1,
+                _ => 2,
+            };
+            let mut color = material.color.value(Vector2::zeros(), p);
+            color[channel] = (color[channel] + direction * COLOR_STEP).clamp(0.0, 255.0);
+            material.color = Arc::new(SolidColor::new(color));
+        }
+        MaterialProperty::Specular => {
+            material.specular = (material.specular * SPECULAR_FACTOR.powf(direction)).max(1.0)
+        }
+        MaterialProperty::Reflective => {
+            material.reflective =
+                (material.reflective + direction * REFLECTIVE_STEP).clamp(0.0, 1.0)
+        }
+        MaterialProperty::Refractive => {
+            material.refractive = (material.refractive + direction * REFRACTIVE_STEP).max(1.0)
+        }
+    }
+}
+
+/// The editable values, for printing after an edit.
+pub fn describe_material(material: &Material, p: Vector3<f32>) -> String {
+    let color = material.color.value(Vector2::zeros(), p);
+    format!(
+        "color ({:.0}, {:.0}, {:.0}), specular {:.1}, reflective {:.2}, refractive {:.2}",
+        color.x, color.y, color.z, material.specular, material.reflective, material.refractive
+    )
+}
+
+/// Draws an outline around the pixels of object `selected`, found through the object id pass.
+pub fn highlight(image: &mut Framebuffer, object_id: &[f32], selected: usize) {
+    let (width, height) = (image.width as i32, image.height as i32);
+    let is_selected = |x: i32, y: i32| {
+        x >= 0
+            && y >= 0
+            && x < width
+            && y < height
+            && object_id[(y * width + x) as usize] == selected as f32
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let edge = is_selected(x, y)
+                && !(is_selected(x - 1, y)
+                    && is_selected(x + 1, y)
+                    && is_selected(x, y - 1)
+                    && is_selected(x, y + 1));
+            if edge {
+                image.set(x as u32, y as u32, Vector3::from(HIGHLIGHT_COLOR));
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::aov::Aovs;
+use crate::framebuffer::Framebuffer;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// the first, instant preview is traced at 1/PREVIEW_SCALE of the resolution
+const PREVIEW_SCALE: u32 = 8;
+
+/// Renders one sample per pixel at the given width and height, with the auxiliary passes if
+/// asked for. Captures everything it needs, e.g. scene, camera and render mode.
+pub type RenderFn = Arc<dyn Fn(u32, u32, bool) -> (Framebuffer, Option<Aovs>) + Send + Sync>;
+
+/// What the viewer shows: the mean of the samples so far, or the low resolution preview
+/// before the first full pass is done.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub image: Framebuffer,
+    /// Rendered with the first full resolution pass, None for the preview.
+    pub aovs: Option<Aovs>,
+    pub samples: u32,
+    /// How long the last pass took.
+    pub pass_time: Duration,
+}
+
+struct State {
+    render: Option<RenderFn>,
+    max_samples: u32,
+    // bumped on every restart, passes of an older generation are thrown away
+    generation: u64,
+    // bumped whenever there is something new to show
+    version: u64,
+    preview: Option<Framebuffer>,
+    sum: Option<Framebuffer>,
+    samples: u32,
+    aovs: Option<Aovs>,
+    pass_time: Duration,
+    stop: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+/// Accumulates samples on a background thread, so the viewer stays responsive while the image
+/// refines.
+pub struct ProgressiveRenderer {
+    width: u32,
+    height: u32,
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl ProgressiveRenderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                render: None,
+                max_samples: 0,
+                generation: 0,
+                version: 0,
+                preview: None,
+                sum: None,
+                samples: 0,
+                aovs: None,
+                pass_time: Duration::default(),
+                stop: false,
+            }),
+            wake: Condvar::new(),
+        });
+
+        let worker_shared = Arc::clone(&shared);
+        let worker = thread::spawn(move || work(&worker_shared, width, height));
+
+        ProgressiveRenderer {
+            width,
+            height,
+            shared,
+            worker: Some(worker),
+        }
+    }
+
+    /// Throws away what was accumulated and starts over with `render`, e.g. after the camera
+    /// moved. Stops refining after `max_samples` passes.
+    pub fn restart(&self, render: RenderFn, max_samples: u32) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.render = Some(render);
+        state.max_samples = max_samples;
+        state.generation += 1;
+        state.version += 1;
+        state.preview = None;
+        state.sum = None;
+        state.samples = 0;
+        state.aovs = None;
+        self.shared.wake.notify_all();
+    }
+
+    /// The current frame if it changed since `seen_version`, which is then updated.
+    pub fn latest(&self, seen_version: &mut u64) -> Option<Frame> {
+        let state = self.shared.state.lock().unwrap();
+        if state.version == *seen_version {
+            return None;
+        }
+
+        let image = match (&state.sum, &state.preview) {
+            (Some(sum), _) => Framebuffer {
+                width: sum.width,
+                height: sum.height,
+                pixels: sum
+                    .pixels
+                    .iter()
+                    .map(|p| p / state.samples as f32)
+                    .collect(),
+            },
+            (None, Some(preview)) => enlarge(preview, self.width, self.height),
+            // restarted, nothing rendered yet
+            (None, None) => return None,
+        };
+
+        *seen_version = state.version;
+        Some(Frame {
+            image,
+            aovs: state.aovs.clone(),
+            samples: state.samples,
+            pass_time: state.pass_time,
+        })
+    }
+}
+
+impl Drop for ProgressiveRenderer {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().stop = true;
+        self.shared.wake.notify_all();
+        if let Some(worker) = self.worker.take() {
+            // a pass in flight still has to finish
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work(shared: &Shared, width: u32, height: u32) {
+    loop {
+        let (render, generation, preview_done, first_pass) = {
+            let mut state = shared.state.lock().unwrap();
+            while !state.stop && (state.render.is_none() || state.samples >= state.max_samples) {
+                state = shared.wake.wait(state).unwrap();
+            }
+            if state.stop {
+                return;
+            }
+            (
+                state.render.clone().unwrap(),
+                state.generation,
+                state.preview.is_some(),
+                state.sum.is_none(),
+            )
+        };
+
+        let start = Instant::now();
+        if !preview_done {
+            let (preview, _) = render(
+                (width / PREVIEW_SCALE).max(1),
+                (height / PREVIEW_SCALE).max(1),
+                false,
+            );
+            let mut state = shared.state.lock().unwrap();
+            if state.generation == generation {
+                state.preview = Some(preview);
+                state.pass_time = start.elapsed();
+                state.version += 1;
+            }
+            continue;
+        }
+
+        let (image, aovs) = render(width, height, first_pass);
+        let mut state = shared.state.lock().unwrap();
+        if state.generation != generation {
+            continue;
+        }
+
+        match &mut state.sum {
+            Some(sum) => {
+                for (total, pixel) in sum.pixels.iter_mut().zip(image.pixels.iter()) {
+                    *total += pixel;
+                }
+            }
+            None => state.sum = Some(image),
+        }
+        if aovs.is_some() {
+            state.aovs = aovs;
+        }
+        state.samples += 1;
+        state.pass_time = start.elapsed();
+        state.version += 1;
+    }
+}
+
+/// Nearest neighbour upscaling of the preview to the full resolution.
+fn enlarge(image: &Framebuffer, width: u32, height: u32) -> Framebuffer {
+    let mut enlarged = Framebuffer::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let source_x = (x * image.width / width).min(image.width - 1);
+            let source_y = (y * image.height / height).min(image.height - 1);
+            enlarged.set(x, y, image.get(source_x, source_y));
+        }
+    }
+    enlarged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_samples_are_averaged_and_reset_on_restart() {
+        let renderer = ProgressiveRenderer::new(4, 2);
+        let constant = |value: f32| -> RenderFn {
+            Arc::new(move |width, height, _| {
+                let mut image = Framebuffer::new(width, height);
+                image
+                    .pixels
+                    .iter_mut()
+                    .for_each(|p| *p = Vector3::repeat(value));
+                (image, None)
+            })
+        };
+
+        // fail instead of hanging the test run when a pass never arrives
+        let wait_for = |samples: u32, seen: &mut u64| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                if let Some(frame) = renderer.latest(seen) {
+                    if frame.samples == samples {
+                        return frame;
+                    }
+                }
+                if Instant::now() > deadline {
+                    panic!("no frame with {} samples after 5 s", samples);
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        };
+
+        let mut seen = 0;
+        renderer.restart(constant(0.5), 3);
+        let frame = wait_for(3, &mut seen);
+        assert_eq!(frame.image.get(3, 1), Vector3::repeat(0.5));
+
+        renderer.restart(constant(2.0), 1);
+        let frame = wait_for(1, &mut seen);
+        assert_eq!(frame.image.get(0, 0), Vector3::repeat(2.0));
+    }
+}
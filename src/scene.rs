@@ -2,22 +2,39 @@ use nalgebra::Vector3;
 
 use crate::intersections::Intersectable;
 use crate::lights::Light;
+use crate::media::Medium;
 
 #[derive(Default)]
 pub struct Scene {
     pub objects: Vec<Box<dyn Intersectable>>,
     pub lights: Vec<Box<dyn Light>>,
+    /// Fog filling the whole scene, up to `media::MAX_FOG_DISTANCE` from each ray origin.
+    pub medium: Option<Medium>,
+    // indices of the emissive objects the path tracer samples as lights, kept by `push`
+    emitters: Vec<usize>,
 }
 
 impl Scene {
     pub fn push(&mut self, object: impl Intersectable + 'static) {
+        if object.material().is_emissive() && object.area() > 0.0 {
+            self.emitters.push(self.objects.len());
+        }
         self.objects.push(Box::new(object))
     }
 
+    /// Objects with an emissive material that can be sampled for next event estimation.
+    pub fn emitters(&self) -> &[usize] {
+        &self.emitters
+    }
+
     pub fn add_light(&mut self, light: impl Light + 'static) {
         self.lights.push(Box::new(light))
     }
 
+    pub fn set_medium(&mut self, medium: Medium) {
+        self.medium = Some(medium)
+    }
+
     pub fn get_nth_element_center(&self, n: i32) -> Option<Vector3<f32>> {
         if let Some(obj) = self.objects.get(n as usize) {
             return Some(obj.center());
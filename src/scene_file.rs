@@ -0,0 +1,874 @@
+use crate::framebuffer::srgb_to_linear;
+use crate::heightfield::Heightfield;
+use crate::lights::{AmbientLight, PositionalLight, SpotLight};
+use crate::materials::{Material, NormalMap};
+use crate::media::{ConstantMedium, Medium};
+use crate::mesh::TriangleMesh;
+use crate::noise::{MarbleTexture, NoiseBasis, NoisePattern, NoiseTexture, WoodTexture};
+use crate::scene::Scene;
+use crate::sdf::{
+    op_intersection, op_repeat, op_smooth_subtraction, op_smooth_union, op_subtraction, op_twist,
+    op_union, sd_box, sd_capsule, sd_mandelbulb, sd_plane, sd_sphere, sd_torus, DistanceFunction,
+    SdfShape,
+};
+use crate::shapes::Sphere;
+use crate::spectral::Ior;
+use crate::textures::{
+    CheckerTexture, FilterMode, GradientDirection, GradientTexture, ImageTexture, SolidColor,
+    Texture, WrapMode,
+};
+use crate::volume::{DensityGrid, GridMedium};
+use nalgebra::Vector3;
+use std::collections::HashMap;
+use std::fs;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+// enough detail for the bulb at viewing distance, each iteration costs a few powf calls
+const MANDELBULB_ITERATIONS: u32 = 8;
+const TWIST_STEP_SCALE: f32 = 0.5;
+
+/// Scene read from a text file, one statement per line, `#` starts a comment:
+///
+/// ```text
+/// material NAME R G B SPECULAR REFLECTIVE REFRACTIVE [OPTION]...
+/// metallic_roughness NAME R G B METALLIC ROUGHNESS [OPTION]...
+/// dielectric NAME R G B TINT_DISTANCE ROUGHNESS IOR [OPTION]...
+/// subsurface NAME R G B MEAN_FREE_PATH_R MEAN_FREE_PATH_G MEAN_FREE_PATH_B [OPTION]...
+/// sphere X Y Z RADIUS MATERIAL
+/// sdf X Y Z BOUNDING_RADIUS SHAPE [OPERATION SHAPE]... [twist K] [repeat PX PY PZ] MATERIAL
+/// quad X Y Z UX UY UZ VX VY VZ MATERIAL
+/// mesh PATH MATERIAL
+/// heightfield PATH X Y Z WIDTH HEIGHT DEPTH MATERIAL
+/// medium DENSITY G R G B sphere X Y Z RADIUS|mesh PATH
+/// volume PATH X Y Z WIDTH HEIGHT DEPTH DENSITY G R G B [emission R G B STRENGTH]
+/// point_light X Y Z INTENSITY R G B
+/// spot_light X Y Z DX DY DZ ANGLE SOFTNESS INTENSITY R G B
+/// ambient_light INTENSITY R G B
+/// fog DENSITY G
+/// ```
+///
+/// Colors are 0-255 sRGB like the palette in `colors`, paths are relative to the scene file. A
+/// `quad` is the parallelogram spanned by the two edges from its corner, lit from the side
+/// U x V points to, which makes an area light with an emissive material. A `medium` fills a
+/// sphere or a closed mesh with smoke, DENSITY is its extinction per unit distance, R G B the
+/// color it scatters and G the anisotropy like in `fog`. A `volume` reads a density grid and
+/// stretches it over the box from X Y Z, DENSITY is the extinction where the grid is 1.
+///
+/// `metallic_roughness` materials follow glTF: R G B is the base color, METALLIC and ROUGHNESS
+/// are 0-1. A `dielectric` is glass-like, light that travels TINT_DISTANCE inside it keeps the
+/// color R G B. Its IOR is a number, `diamond`, `crown_glass`, `flint_glass`, `cauchy A B` or
+/// `sellmeier B1 B2 B3 C1 C2 C3` with wavelengths in micrometers. A `subsurface` material like
+/// wax or skin has the color R G B from afar, light travels the mean free path inside it before
+/// scattering, per channel in scene units. Options of all materials:
+///
+/// ```text
+/// texture PATH [repeat|mirror|clamp] [bilinear|nearest]
+/// checker R G B R G B FREQUENCY
+/// gradient u|v R G B R G B
+/// noise perlin|simplex plain|fbm OCTAVES|turbulence OCTAVES SCALE R G B R G B
+/// marble SCALE DISTORTION R G B R G B
+/// wood RING_FREQUENCY DISTORTION R G B R G B
+/// normal_map PATH
+/// bump PATH STRENGTH
+/// coat STRENGTH ROUGHNESS
+/// thin_film THICKNESS IOR
+/// emission STRENGTH
+/// ```
+///
+/// An `sdf` SHAPE is `sphere RADIUS`, `box HX HY HZ`, `torus MAJOR MINOR`,
+/// `capsule AX AY AZ BX BY BZ RADIUS`, `plane NX NY NZ HEIGHT` or `mandelbulb POWER`, centered on
+/// the object. OPERATION combines it with the shapes so far: `union`, `subtract`, `intersect`,
+/// `smooth_union K` or `smooth_subtract K`.
+pub struct SceneFile {
+    pub scene: Scene,
+    /// The scene file and every texture and mesh it references.
+    pub dependencies: Vec<PathBuf>,
+}
+
+pub fn load_scene(path: &str) -> Result<SceneFile, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+
+    let mut scene_file = parse_scene(&text, directory).map_err(|e| format!("{}:{}", path, e))?;
+    scene_file.dependencies.insert(0, PathBuf::from(path));
+    Ok(scene_file)
+}
+
+/// Builds the scene described by `text`, loading referenced files from `directory`.
+pub fn parse_scene(text: &str, directory: &Path) -> Result<SceneFile, String> {
+    let mut scene = Scene::default();
+    let mut materials: HashMap<String, Material> = HashMap::new();
+    let mut dependencies = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = Tokens {
+            tokens: line.split_whitespace().peekable(),
+        };
+        let statement = match tokens.tokens.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+
+        let mut parse_statement = || -> Result<(), String> {
+            match statement {
+                "material" | "metallic_roughness" | "dielectric" | "subsurface" => {
+                    let name = tokens.word()?;
+                    let color = tokens.color()?;
+                    let mut material = match statement {
+                        "material" => {
+                            let (specular, reflective, refractive) =
+                                (tokens.number()?, tokens.number()?, tokens.number()?);
+                            Material::new(color, specular, reflective, refractive)
+                        }
+                        "dielectric" => {
+                            let (tint_distance, roughness) = (tokens.number()?, tokens.number()?);
+                            let ior = tokens.ior()?;
+                            Material::dielectric(ior, roughness, color, tint_distance)
+                        }
+                        "subsurface" => Material::subsurface(color, tokens.vector()?),
+                        _ => {
+                            let (metallic, roughness) = (tokens.number()?, tokens.number()?);
+                            Material::metallic_roughness(
+                                Arc::new(SolidColor::new(color)),
+                                metallic,
+                                roughness,
+                            )
+                        }
+                    };
+
+                    while let Some(option) = tokens.tokens.next() {
+                        match option {
+                            "texture" => {
+                                let path = directory.join(tokens.word()?);
+                                let wrap = match tokens.optional(&["repeat", "mirror", "clamp"]) {
+                                    Some("mirror") => WrapMode::MirroredRepeat,
+                                    Some("clamp") => WrapMode::ClampToEdge,
+                                    _ => WrapMode::Repeat,
+                                };
+                                let filter = match tokens.optional(&["bilinear", "nearest"]) {
+                                    Some("nearest") => FilterMode::Nearest,
+                                    _ => FilterMode::Bilinear,
+                                };
+                                let texture = ImageTexture::from_file(
+                                    &path.to_string_lossy(),
+                                    wrap,
+                                    filter,
+                                    true,
+                                )?;
+                                material.color = Arc::new(texture);
+                                dependencies.push(path);
+                            }
+                            "checker" => {
+                                let (even, odd) = (tokens.color()?, tokens.color()?);
+                                material.color = Arc::new(CheckerTexture::new(
+                                    Arc::new(SolidColor::new(even)),
+                                    Arc::new(SolidColor::new(odd)),
+                                    tokens.number()?,
+                                ));
+                            }
+                            "gradient" => {
+                                let direction = match tokens.word()? {
+                                    "u" => GradientDirection::U,
+                                    "v" => GradientDirection::V,
+                                    direction => {
+                                        return Err(format!("expected u or v, found {}", direction))
+                                    }
+                                };
+                                let (from, to) = (tokens.color()?, tokens.color()?);
+                                material.color =
+                                    Arc::new(GradientTexture::new(from, to, direction));
+                            }
+                            "noise" => {
+                                let basis = match tokens.word()? {
+                                    "perlin" => NoiseBasis::Perlin,
+                                    "simplex" => NoiseBasis::Simplex,
+                                    basis => return Err(format!("unknown noise {}", basis)),
+                                };
+                                let pattern = match tokens.word()? {
+                                    "plain" => NoisePattern::Plain,
+                                    "fbm" => NoisePattern::Fbm {
+                                        octaves: tokens.number()? as u32,
+                                    },
+                                    "turbulence" => NoisePattern::Turbulence {
+                                        octaves: tokens.number()? as u32,
+                                    },
+                                    pattern => {
+                                        return Err(format!("unknown noise pattern {}", pattern))
+                                    }
+                                };
+                                let scale = tokens.number()?;
+                                let (low, high) = (tokens.color()?, tokens.color()?);
+                                material.color =
+                                    Arc::new(NoiseTexture::new(basis, pattern, scale, low, high));
+                            }
+                            "marble" => {
+                                let (scale, distortion) = (tokens.number()?, tokens.number()?);
+                                let (base, vein) = (tokens.color()?, tokens.color()?);
+                                material.color =
+                                    Arc::new(MarbleTexture::new(scale, distortion, base, vein));
+                            }
+                            "wood" => {
+                                let (ring_frequency, distortion) =
+                                    (tokens.number()?, tokens.number()?);
+                                let (light, dark) = (tokens.color()?, tokens.color()?);
+                                material.color = Arc::new(WoodTexture::new(
+                                    ring_frequency,
+                                    distortion,
+                                    light,
+                                    dark,
+                                ));
+                            }
+                            "normal_map" | "bump" => {
+                                let path = directory.join(tokens.word()?);
+                                // the map holds directions or heights, not colors, so it is
+                                // used as stored
+                                let texture: Arc<dyn Texture> = Arc::new(ImageTexture::from_file(
+                                    &path.to_string_lossy(),
+                                    WrapMode::Repeat,
+                                    FilterMode::Bilinear,
+                                    false,
+                                )?);
+                                let normal_map = if option == "bump" {
+                                    NormalMap::Bump {
+                                        height: texture,
+                                        strength: tokens.number()?,
+                                    }
+                                } else {
+                                    NormalMap::TangentSpace(texture)
+                                };
+                                material = material.with_normal_map(normal_map);
+                                dependencies.push(path);
+                            }
+                            "coat" => {
+                                let strength = tokens.number()?;
+                                material = material.with_clear_coat(strength, tokens.number()?)
+                            }
+                            "thin_film" => {
+                                let thickness = tokens.number()?;
+                                material = material.with_thin_film(thickness, tokens.number()?)
+                            }
+                            "emission" => {
+                                material = material.with_emission(color, tokens.number()?)
+                            }
+                            _ => return Err(format!("unknown material option {}", option)),
+                        }
+                    }
+                    materials.insert(name.to_string(), material);
+                }
+                "sphere" => {
+                    let center = tokens.vector()?;
+                    let radius = tokens.number()?;
+                    let material = tokens.material(&materials)?;
+                    scene.push(Sphere::with_material(center, radius, material));
+                }
+                "sdf" => {
+                    let center = tokens.vector()?;
+                    let bounding_radius = tokens.number()?;
+                    let (distance, step_scale, material) = tokens.sdf()?;
+                    let material = find_material(&materials, material)?;
+                    let mut shape = SdfShape::new(center, bounding_radius, distance, material);
+                    shape.step_scale = step_scale;
+                    scene.push(shape);
+                }
+                "quad" => {
+                    let corner = tokens.vector()?;
+                    let (edge_u, edge_v) = (tokens.vector()?, tokens.vector()?);
+                    let material = tokens.material(&materials)?;
+                    scene.push(TriangleMesh::quad(corner, edge_u, edge_v, material));
+                }
+                "mesh" => {
+                    let path = directory.join(tokens.word()?);
+                    let material = tokens.material(&materials)?;
+                    scene.push(TriangleMesh::from_obj(&path.to_string_lossy(), material)?);
+                    dependencies.push(path);
+                }
+                "heightfield" => {
+                    let path = directory.join(tokens.word()?);
+                    let (origin, size) = (tokens.vector()?, tokens.vector()?);
+                    let material = tokens.material(&materials)?;
+                    scene.push(Heightfield::from_image(
+                        &path.to_string_lossy(),
+                        origin,
+                        size,
+                        material,
+                    )?);
+                    dependencies.push(path);
+                }
+                "medium" => {
+                    let (density, g) = (tokens.number()?, tokens.number()?);
+                    let albedo = tokens.color()? / 255.0;
+                    let medium = Medium::new(
+                        (Vector3::repeat(1.0) - albedo) * density,
+                        albedo * density,
+                        g,
+                    );
+                    // the boundary is never shaded, only the medium inside it
+                    let boundary_material = Material::new(Vector3::zeros(), 0.0, 0.0, 1.0);
+                    match tokens.word()? {
+                        "sphere" => {
+                            let center = tokens.vector()?;
+                            let radius = tokens.number()?;
+                            let boundary = Sphere::with_material(center, radius, boundary_material);
+                            scene.push(ConstantMedium::new(boundary, medium));
+                        }
+                        "mesh" => {
+                            let path = directory.join(tokens.word()?);
+                            let boundary =
+                                TriangleMesh::from_obj(&path.to_string_lossy(), boundary_material)?;
+                            scene.push(ConstantMedium::new(boundary, medium));
+                            dependencies.push(path);
+                        }
+                        shape => return Err(format!("expected sphere or mesh, found {}", shape)),
+                    }
+                }
+                "volume" => {
+                    let path = directory.join(tokens.word()?);
+                    let (origin, size) = (tokens.vector()?, tokens.vector()?);
+                    let (density, g) = (tokens.number()?, tokens.number()?);
+                    let albedo = tokens.color()? / 255.0;
+                    let medium = Medium::new(
+                        (Vector3::repeat(1.0) - albedo) * density,
+                        albedo * density,
+                        g,
+                    );
+                    let grid = DensityGrid::from_file(&path.to_string_lossy())?;
+                    // the sphere around the grid box, the density is zero outside the box
+                    let boundary = Sphere::with_material(
+                        origin + size / 2.0,
+                        size.norm() / 2.0,
+                        Material::new(Vector3::zeros(), 0.0, 0.0, 1.0),
+                    );
+                    let mut volume = GridMedium::new(boundary, grid, origin, size, medium);
+                    if tokens.optional(&["emission"]).is_some() {
+                        let color = tokens.color()?;
+                        volume = volume.with_emission(color, tokens.number()?);
+                    }
+                    scene.push(volume);
+                    dependencies.push(path);
+                }
+                "point_light" => {
+                    let center = tokens.vector()?;
+                    let intensity = tokens.number()?;
+                    scene.add_light(PositionalLight::new(center, intensity, tokens.color()?));
+                }
+                "spot_light" => {
+                    let center = tokens.vector()?;
+                    let direction = tokens.vector()?;
+                    let (angle, softness, intensity) =
+                        (tokens.number()?, tokens.number()?, tokens.number()?);
+                    scene.add_light(SpotLight::new(
+                        center,
+                        direction,
+                        angle,
+                        softness,
+                        intensity,
+                        tokens.color()?,
+                    ));
+                }
+                "ambient_light" => {
+                    let intensity = tokens.number()?;
+                    scene.add_light(AmbientLight::new(intensity, tokens.color()?));
+                }
+                "fog" => {
+                    let density = tokens.number()?;
+                    scene.set_medium(Medium::fog(density, tokens.number()?));
+                }
+                _ => return Err(format!("unknown statement {}", statement)),
+            }
+
+            match tokens.tokens.next() {
+                Some(extra) => Err(format!("unexpected {}", extra)),
+                None => Ok(()),
+            }
+        };
+
+        parse_statement().map_err(|e| format!("{}: {}", number + 1, e))?;
+    }
+
+    Ok(SceneFile {
+        scene,
+        dependencies,
+    })
+}
+
+struct Tokens<'a> {
+    tokens: Peekable<SplitWhitespace<'a>>,
+}
+
+impl<'a> Tokens<'a> {
+    fn word(&mut self) -> Result<&'a str, String> {
+        self.tokens
+            .next()
+            .ok_or_else(|| "line ends too early".to_string())
+    }
+
+    /// The next word if it is one of `words`, which makes it optional.
+    fn optional(&mut self, words: &[&str]) -> Option<&'a str> {
+        let word = *self.tokens.peek()?;
+        if words.contains(&word) {
+            self.tokens.next()
+        } else {
+            None
+        }
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| format!("expected a number, found {}", word))
+    }
+
+    fn vector(&mut self) -> Result<Vector3<f32>, String> {
+        Ok(Vector3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    /// A number, or a named glass or dispersion formula for the spectral mode.
+    fn ior(&mut self) -> Result<Ior, String> {
+        match self.optional(&[
+            "diamond",
+            "crown_glass",
+            "flint_glass",
+            "cauchy",
+            "sellmeier",
+        ]) {
+            Some("diamond") => Ok(Ior::diamond()),
+            Some("crown_glass") => Ok(Ior::crown_glass()),
+            Some("flint_glass") => Ok(Ior::flint_glass()),
+            Some("cauchy") => Ok(Ior::Cauchy {
+                a: self.number()?,
+                b: self.number()?,
+            }),
+            Some(_) => Ok(Ior::Sellmeier {
+                b: [self.number()?, self.number()?, self.number()?],
+                c: [self.number()?, self.number()?, self.number()?],
+            }),
+            None => Ok(Ior::Constant(self.number()?)),
+        }
+    }
+
+    /// sRGB 0-255 to the renderer's linear 0-255.
+    fn color(&mut self) -> Result<Vector3<f32>, String> {
+        Ok(self.vector()?.map(|c| srgb_to_linear(c / 255.0) * 255.0))
+    }
+
+    fn material(&mut self, materials: &HashMap<String, Material>) -> Result<Material, String> {
+        let name = self.word()?;
+        find_material(materials, name)
+    }
+
+    /// The distance function of an `sdf` statement, the step scale it needs and the word after
+    /// it, which names the material.
+    fn sdf(&mut self) -> Result<(Box<DistanceFunction>, f32, &'a str), String> {
+        let first = self.word()?;
+        let mut distance = self.sdf_shape(first)?;
+        let mut step_scale = 1.0;
+
+        loop {
+            let word = self.word()?;
+            distance = match word {
+                "union" | "subtract" | "intersect" => {
+                    let name = self.word()?;
+                    let other = self.sdf_shape(name)?;
+                    match word {
+                        "union" => Box::new(move |p| op_union(distance(p), other(p))),
+                        // carves the new shape out of the shapes so far
+                        "subtract" => Box::new(move |p| op_subtraction(other(p), distance(p))),
+                        _ => Box::new(move |p| op_intersection(distance(p), other(p))),
+                    }
+                }
+                "smooth_union" | "smooth_subtract" => {
+                    let k = self.number()?;
+                    let name = self.word()?;
+                    let other = self.sdf_shape(name)?;
+                    if word == "smooth_union" {
+                        Box::new(move |p| op_smooth_union(distance(p), other(p), k))
+                    } else {
+                        Box::new(move |p| op_smooth_subtraction(other(p), distance(p), k))
+                    }
+                }
+                "twist" => {
+                    let k = self.number()?;
+                    // the twist stretches the field, smaller steps keep the marching from
+                    // overshooting
+                    step_scale = TWIST_STEP_SCALE;
+                    Box::new(move |p| distance(op_twist(p, k)))
+                }
+                "repeat" => {
+                    let period = self.vector()?;
+                    Box::new(move |p| distance(op_repeat(p, period)))
+                }
+                _ => return Ok((distance, step_scale, word)),
+            };
+        }
+    }
+
+    fn sdf_shape(&mut self, name: &str) -> Result<Box<DistanceFunction>, String> {
+        Ok(match name {
+            "sphere" => {
+                let radius = self.number()?;
+                Box::new(move |p| sd_sphere(p, radius))
+            }
+            "box" => {
+                let half_extents = self.vector()?;
+                Box::new(move |p| sd_box(p, half_extents))
+            }
+            "torus" => {
+                let (major_radius, minor_radius) = (self.number()?, self.number()?);
+                Box::new(move |p| sd_torus(p, major_radius, minor_radius))
+            }
+            "capsule" => {
+                let (a, b, radius) = (self.vector()?, self.vector()?, self.number()?);
+                Box::new(move |p| sd_capsule(p, a, b, radius))
+            }
+            "plane" => {
+                let (normal, height) = (self.vector()?, self.number()?);
+                Box::new(move |p| sd_plane(p, normal, height))
+            }
+            "mandelbulb" => {
+                let power = self.number()?;
+                Box::new(move |p| sd_mandelbulb(p, power, MANDELBULB_ITERATIONS))
+            }
+            _ => return Err(format!("unknown sdf shape {}", name)),
+        })
+    }
+}
+
+fn find_material(materials: &HashMap<String, Material>, name: &str) -> Result<Material, String> {
+    materials
+        .get(name)
+        .cloned()
+        .ok_or(format!("unknown material {}", name))
+}
+
+/// Polls the modification times of a scene's files.
+pub struct SceneWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl SceneWatcher {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        SceneWatcher {
+            files: paths
+                .iter()
+                .map(|path| (path.clone(), modified(path)))
+                .collect(),
+        }
+    }
+
+    /// Whether any file changed, appeared or disappeared since the last call.
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last_modified) in self.files.iter_mut() {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{ClearCoat, ThinFilm};
+    use crate::materials::ShadingModel;
+    use crate::ray::Ray;
+    use nalgebra::Vector2;
+
+    #[test]
+    fn test_parse_scene() {
+        let text = "
+            # a red ball on a light
+            material red 255 0 0 100 0.2 1.5
+            sphere 0 0 5 1 red
+            point_light 0 5 0 0.8 255 255 255
+            ambient_light 0.2 255 255 255
+        ";
+        let scene_file = parse_scene(text, Path::new("")).unwrap();
+
+        assert_eq!(scene_file.scene.objects.len(), 1);
+        assert_eq!(scene_file.scene.lights.len(), 2);
+        assert_eq!(
+            scene_file.scene.objects[0].center(),
+            Vector3::new(0.0, 0.0, 5.0)
+        );
+        assert!(scene_file.dependencies.is_empty());
+    }
+
+    #[test]
+    fn test_sdf_statement() {
+        let text = "
+            material white 255 255 255 10 0 1
+            sdf 4 0 0 2 box 1 1 1 subtract sphere 1.2 white
+            sdf 0 4 0 1 torus 0.5 0.2 twist 2 repeat 0.5 0 0 white
+        ";
+        let scene_file = parse_scene(text, Path::new("")).unwrap();
+        assert_eq!(scene_file.scene.objects.len(), 2);
+        let shape = &scene_file.scene.objects[0];
+
+        // the sphere carves the middle out of the box, only its corners are left
+        let through_middle = Ray::new(Vector3::zeros(), Vector3::new(1.0, 0.0, 0.0));
+        assert!(shape.intersect(&through_middle, 0.001, f32::MAX).is_none());
+        let through_corner = Ray::new(Vector3::new(0.0, 0.9, 0.9), Vector3::new(1.0, 0.0, 0.0));
+        let hit = shape.intersect(&through_corner, 0.001, f32::MAX).unwrap();
+        assert!((hit.intersection_point - 3.0).abs() < 1e-3);
+
+        assert!(parse_scene(
+            "material m 1 2 3 4 5 6\nsdf 0 0 0 1 cone 1 m",
+            Path::new("")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_procedural_textures() {
+        let text = "
+            material board 255 255 255 10 0 1 checker 255 255 255 0 0 0 4
+            material sunset 0 0 0 10 0 1 gradient v 255 0 0 0 0 255
+            sphere 0 0 5 1 board
+            sphere 0 0 9 1 sunset
+        ";
+        let scene = parse_scene(text, Path::new("")).unwrap().scene;
+
+        let color = |object: usize, u: f32, v: f32| {
+            scene.objects[object]
+                .material()
+                .color_at(Vector2::new(u, v), Vector3::zeros())
+        };
+        assert_ne!(color(0, 0.1, 0.1), color(0, 0.3, 0.1));
+        assert_eq!(color(1, 0.5, 0.0), Vector3::new(255.0, 0.0, 0.0));
+        assert_eq!(color(1, 0.5, 1.0), Vector3::new(0.0, 0.0, 255.0));
+
+        let text = "material m 1 2 3 4 5 6 gradient w 0 0 0 1 1 1";
+        assert!(parse_scene(text, Path::new("")).is_err());
+    }
+
+    #[test]
+    fn test_noise_textures() {
+        let text = "
+            material clouds 255 255 255 10 0 1 noise simplex fbm 5 4 0 0 0 255 255 255
+            material stone 255 255 255 10 0 1 marble 8 5 230 230 230 60 60 80
+            material oak 255 255 255 10 0 1 wood 12 0.4 200 150 90 110 70 30
+            sphere 0 0 5 1 clouds
+            sphere 0 0 9 1 stone
+            sphere 0 0 13 1 oak
+        ";
+        let scene = parse_scene(text, Path::new("")).unwrap().scene;
+
+        // solid textures vary through space, not over the surface parametrization
+        for object in &scene.objects {
+            let color = |p: Vector3<f32>| object.material().color_at(Vector2::zeros(), p);
+            assert_ne!(
+                color(Vector3::new(0.13, 0.27, 0.41)),
+                color(Vector3::new(0.52, -0.31, 0.08))
+            );
+        }
+
+        let text = "material m 1 2 3 4 5 6 noise value plain 1 0 0 0 1 1 1";
+        assert!(parse_scene(text, Path::new("")).is_err());
+    }
+
+    #[test]
+    fn test_metallic_roughness_statement() {
+        let text = "
+            metallic_roughness gold 255 200 80 1 0.3 bump missing.png 1
+        ";
+        // options go through the same parsing as for Phong materials
+        assert!(parse_scene(text, Path::new(""))
+            .err()
+            .unwrap()
+            .contains("missing.png"));
+
+        let text = "
+            metallic_roughness gold 255 200 80 1 0.3
+            sphere 0 0 5 1 gold
+        ";
+        let scene = parse_scene(text, Path::new("")).unwrap().scene;
+        assert_eq!(
+            scene.objects[0].material().model,
+            ShadingModel::MetallicRoughness {
+                metallic: 1.0,
+                roughness: 0.3
+            }
+        );
+    }
+
+    #[test]
+    fn test_coating_options() {
+        let text = "
+            material lacquer 200 30 30 40 0 1 coat 0.8 0.1 thin_film 350 1.33
+            sphere 0 0 5 1 lacquer
+        ";
+        let scene = parse_scene(text, Path::new("")).unwrap().scene;
+        let coating = scene.objects[0].material().coating;
+        assert_eq!(
+            coating.clear_coat,
+            Some(ClearCoat {
+                strength: 0.8,
+                roughness: 0.1
+            })
+        );
+        assert_eq!(
+            coating.thin_film,
+            Some(ThinFilm {
+                thickness: 350.0,
+                ior: 1.33
+            })
+        );
+    }
+
+    #[test]
+    fn test_subsurface_statement() {
+        let text = "
+            subsurface wax 255 255 255 0.05 0.03 0.02
+            sphere 0 0 5 1 wax
+        ";
+        let scene = parse_scene(text, Path::new("")).unwrap().scene;
+        assert_eq!(
+            scene.objects[0].material().model,
+            ShadingModel::Subsurface {
+                mean_free_path: Vector3::new(0.05, 0.03, 0.02)
+            }
+        );
+    }
+
+    #[test]
+    fn test_medium_statement() {
+        let text = "medium 2 0.3 255 255 255 sphere 0 0 5 1";
+        let scene = parse_scene(text, Path::new("")).unwrap().scene;
+        let smoke = &scene.objects[0];
+        assert_eq!(smoke.material().model, ShadingModel::Volume { g: 0.3 });
+
+        // a white medium only scatters, light crossing the sphere goes through 2 units of it
+        let ray = Ray::new(Vector3::zeros(), Vector3::new(0.0, 0.0, 1.0));
+        let transmittance = smoke.transmittance(&ray, 0.0, f32::MAX);
+        assert!((transmittance - f32::exp(-4.0)).abs() < 1e-4);
+
+        assert!(parse_scene("medium 2 0.3 255 255 255 cube 1", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn test_dielectric_statement() {
+        let text = "
+            dielectric amber 255 160 40 0.5 0.1 1.55
+            sphere 0 0 5 1 amber
+        ";
+        let scene = parse_scene(text, Path::new("")).unwrap().scene;
+        match scene.objects[0].material().model {
+            ShadingModel::Dielectric {
+                ior,
+                roughness,
+                absorption,
+            } => {
+                assert_eq!(ior.nominal(), 1.55);
+                assert_eq!(roughness, 0.1);
+                // blue is absorbed the most
+                assert!(absorption.z > absorption.y && absorption.y > absorption.x);
+            }
+            model => panic!("expected a dielectric, found {:?}", model),
+        }
+    }
+
+    #[test]
+    fn test_dielectric_ior_models() {
+        let text = "
+            dielectric a 255 255 255 1 0 diamond
+            dielectric b 255 255 255 1 0 cauchy 1.5 0.004
+            dielectric c 255 255 255 1 0 sellmeier 1 0.2 1 0.006 0.02 100
+            sphere 0 0 5 1 a
+            sphere 0 0 8 1 b
+            sphere 0 0 11 1 c
+        ";
+        let scene = parse_scene(text, Path::new("")).unwrap().scene;
+        let iors: Vec<Ior> = scene
+            .objects
+            .iter()
+            .map(|object| match object.material().model {
+                ShadingModel::Dielectric { ior, .. } => ior,
+                model => panic!("expected a dielectric, found {:?}", model),
+            })
+            .collect();
+
+        assert_eq!(iors[0], Ior::diamond());
+        assert_eq!(iors[1], Ior::Cauchy { a: 1.5, b: 0.004 });
+        assert_eq!(
+            iors[2],
+            Ior::Sellmeier {
+                b: [1.0, 0.2, 1.0],
+                c: [0.006, 0.02, 100.0]
+            }
+        );
+        // blue bends more than red
+        assert!(iors[2].at(450.0) > iors[2].at(650.0));
+    }
+
+    #[test]
+    fn test_heightfield_image_is_a_dependency() {
+        let directory = std::env::temp_dir();
+        let path = directory.join("scene_file_test_heights.png");
+        image::GrayImage::from_fn(4, 4, |x, _| image::Luma([x as u8 * 60]))
+            .save(&path)
+            .unwrap();
+
+        let text = "
+            material grass 90 140 60 20 0 1
+            heightfield scene_file_test_heights.png 0 -1 0 4 1 4 grass
+        ";
+        let scene_file = parse_scene(text, &directory).unwrap();
+        assert_eq!(scene_file.scene.objects.len(), 1);
+        assert_eq!(scene_file.dependencies, vec![path.clone()]);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_volume_grid_is_a_dependency() {
+        let directory = std::env::temp_dir();
+        let path = directory.join("scene_file_test_cloud.density");
+        let mut bytes = b"DENSITY 2 2 2\n".to_vec();
+        for _ in 0..8 {
+            bytes.extend_from_slice(&1.0f32.to_le_bytes());
+        }
+        fs::write(&path, bytes).unwrap();
+
+        let text = "volume scene_file_test_cloud.density 0 0 4 2 2 2 3 0 255 255 255";
+        let scene_file = parse_scene(text, &directory).unwrap();
+        assert_eq!(scene_file.dependencies, vec![path.clone()]);
+
+        // through the middle of the box, a uniform grid is as thick as a constant medium
+        let ray = Ray::new(Vector3::new(1.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        let samples = 2000;
+        let transmittance = (0..samples)
+            .map(|_| scene_file.scene.objects[0].transmittance(&ray, 0.0, f32::MAX))
+            .sum::<f32>()
+            / samples as f32;
+        assert!((transmittance - f32::exp(-6.0)).abs() < 0.01);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_errors_name_the_line() {
+        let error = parse_scene(
+            "material m 1 2 3 4 5 6\nsphere 0 0 0 1 missing",
+            Path::new(""),
+        )
+        .err()
+        .unwrap();
+        assert!(error.starts_with("2:"));
+        assert!(error.contains("missing"));
+    }
+}
@@ -0,0 +1,313 @@
+use crate::intersections::{tangent_frame, Intersectable, IntersectionRecord};
+use crate::materials::Material;
+use crate::noise::lerp;
+use crate::ray::Ray;
+use crate::shapes::sphere_uv;
+use nalgebra::{Vector2, Vector3};
+
+const MAX_MARCHING_STEPS: u32 = 256;
+const SURFACE_EPSILON: f32 = 1e-4;
+const NORMAL_EPSILON: f32 = 1e-4;
+
+pub type DistanceFunction = dyn Fn(Vector3<f32>) -> f32 + Send + Sync;
+
+/// Shape described by a signed distance function, rendered by sphere tracing.
+///
+/// The distance function is evaluated in object space, i.e. relative to `center`,
+/// and the shape is assumed to fit inside a sphere of `bounding_radius` around it.
+pub struct SdfShape {
+    pub center: Vector3<f32>,
+    pub bounding_radius: f32,
+    pub material: Material,
+    // fraction of the distance bound used per step, lower it for distorted fields (twist)
+    pub step_scale: f32,
+    distance: Box<DistanceFunction>,
+}
+
+impl SdfShape {
+    pub fn new(
+        center: Vector3<f32>,
+        bounding_radius: f32,
+        distance: impl Fn(Vector3<f32>) -> f32 + Send + Sync + 'static,
+        material: Material,
+    ) -> Self {
+        SdfShape {
+            center,
+            bounding_radius,
+            material,
+            step_scale: 1.0,
+            distance: Box::new(distance),
+        }
+    }
+
+    pub fn distance(&self, p: Vector3<f32>) -> f32 {
+        (self.distance)(p - self.center)
+    }
+
+    pub fn normal(&self, p: Vector3<f32>) -> Vector3<f32> {
+        // tetrahedron technique: four evaluations instead of six for central differences
+        let k1 = Vector3::new(1.0, -1.0, -1.0);
+        let k2 = Vector3::new(-1.0, -1.0, 1.0);
+        let k3 = Vector3::new(-1.0, 1.0, -1.0);
+        let k4 = Vector3::new(1.0, 1.0, 1.0);
+
+        let n = k1 * self.distance(p + k1 * NORMAL_EPSILON)
+            + k2 * self.distance(p + k2 * NORMAL_EPSILON)
+            + k3 * self.distance(p + k3 * NORMAL_EPSILON)
+            + k4 * self.distance(p + k4 * NORMAL_EPSILON);
+
+        n.normalize()
+    }
+
+    /// Ray parameter range in which the ray is inside the bounding sphere.
+    fn bounding_interval(&self, ray: &Ray) -> Option<(f32, f32)> {
+        let ray_to_center = ray.origin() - self.center;
+
+        let a = ray.direction().dot(&ray.direction());
+        let b = ray_to_center.dot(&ray.direction());
+        let c = ray_to_center.dot(&ray_to_center) - self.bounding_radius.powi(2);
+
+        let delta = b.powi(2) - a * c;
+        if delta <= 0.0 {
+            return None;
+        }
+
+        let delta_squared = f32::sqrt(delta);
+        Some(((-b - delta_squared) / a, (-b + delta_squared) / a))
+    }
+}
+
+impl Intersectable for SdfShape {
+    fn center(&self) -> Vector3<f32> {
+        self.center
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<IntersectionRecord> {
+        let (t_enter, t_exit) = self.bounding_interval(ray)?;
+        let t_end = t_exit.min(t_max);
+
+        // ray directions are not normalized, distances are in world units
+        let direction_length = ray.direction().norm();
+        let mut t = t_enter.max(t_min);
+
+        // secondary rays leave from the surface itself, step off it before marching
+        if (t - t_min).abs() < f32::EPSILON
+            && self.distance(ray.point_at_parameter(t)).abs() < SURFACE_EPSILON
+        {
+            t += 2.0 * SURFACE_EPSILON / direction_length;
+        }
+
+        for _ in 0..MAX_MARCHING_STEPS {
+            if t > t_end {
+                return None;
+            }
+
+            let p = ray.point_at_parameter(t);
+            // abs() lets refracted rays march from inside the shape
+            let d = self.distance(p).abs();
+
+            if d < SURFACE_EPSILON {
+                // no natural parametrization, project the hit point onto the bounding sphere
+                let local = p - self.center;
+                let uv = sphere_uv(local / local.norm().max(f32::EPSILON));
+                let normal = self.normal(p);
+                let (tangent, bitangent) =
+                    tangent_frame(normal, Vector3::new(-local.z, 0.0, local.x));
+
+                return Some(IntersectionRecord {
+                    intersection_point: t,
+                    intersection_vector: p,
+                    object_center: self.center(),
+                    object_index: 0,
+                    normal: self
+                        .material
+                        .shading_normal(uv, local, normal, tangent, bitangent),
+                    geometric_normal: normal,
+                    object_color: self.material.color_at(uv, local),
+                    object_specular: self.material.specular,
+                    object_reflective: self.material.reflective,
+                    object_refractive: self.material.refractive,
+                    object_model: self.material.model,
+                    object_emission: self.material.emission,
+                    object_coating: self.material.coating,
+                });
+            }
+
+            t += self.step_scale * d / direction_length;
+        }
+
+        None
+    }
+}
+
+/* primitives, all centered at the origin */
+
+pub fn sd_sphere(p: Vector3<f32>, radius: f32) -> f32 {
+    p.norm() - radius
+}
+
+pub fn sd_box(p: Vector3<f32>, half_extents: Vector3<f32>) -> f32 {
+    let q = p.abs() - half_extents;
+    q.sup(&Vector3::zeros()).norm() + q.max().min(0.0)
+}
+
+pub fn sd_torus(p: Vector3<f32>, major_radius: f32, minor_radius: f32) -> f32 {
+    let q = Vector2::new(Vector2::new(p.x, p.z).norm() - major_radius, p.y);
+    q.norm() - minor_radius
+}
+
+pub fn sd_plane(p: Vector3<f32>, normal: Vector3<f32>, height: f32) -> f32 {
+    p.dot(&normal.normalize()) + height
+}
+
+pub fn sd_capsule(p: Vector3<f32>, a: Vector3<f32>, b: Vector3<f32>, radius: f32) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = (pa.dot(&ba) / ba.dot(&ba)).clamp(0.0, 1.0);
+    (pa - ba * h).norm() - radius
+}
+
+/// Distance estimator for the power-`power` Mandelbulb, bounded by a sphere of radius ~1.2.
+pub fn sd_mandelbulb(p: Vector3<f32>, power: f32, iterations: u32) -> f32 {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = 0.0;
+
+    for _ in 0..iterations {
+        r = z.norm();
+        if r > 2.0 {
+            break;
+        }
+        // the orbit sits on the fixed point at the center, inside the set, where the angles
+        // and the final log would turn into NaN
+        if r < 1e-6 {
+            return 0.0;
+        }
+
+        let theta = f32::acos((z.z / r).clamp(-1.0, 1.0)) * power;
+        let phi = f32::atan2(z.y, z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+
+        let zr = r.powf(power);
+        z =
+            zr * Vector3::new(
+                theta.sin() * phi.cos(),
+                phi.sin() * theta.sin(),
+                theta.cos(),
+            ) + p;
+    }
+
+    0.5 * r.ln() * r / dr
+}
+
+/* combinators */
+
+pub fn op_union(d1: f32, d2: f32) -> f32 {
+    d1.min(d2)
+}
+
+pub fn op_subtraction(d1: f32, d2: f32) -> f32 {
+    // carves d1 out of d2
+    (-d1).max(d2)
+}
+
+pub fn op_intersection(d1: f32, d2: f32) -> f32 {
+    d1.max(d2)
+}
+
+pub fn op_smooth_union(d1: f32, d2: f32, k: f32) -> f32 {
+    let h = (0.5 + 0.5 * (d2 - d1) / k).clamp(0.0, 1.0);
+    lerp(d2, d1, h) - k * h * (1.0 - h)
+}
+
+pub fn op_smooth_subtraction(d1: f32, d2: f32, k: f32) -> f32 {
+    let h = (0.5 - 0.5 * (d2 + d1) / k).clamp(0.0, 1.0);
+    lerp(d2, -d1, h) + k * h * (1.0 - h)
+}
+
+/* domain operations, applied to the point before evaluating a primitive */
+
+/// Repeats space every `period` units along each axis (0.0 disables an axis).
+pub fn op_repeat(p: Vector3<f32>, period: Vector3<f32>) -> Vector3<f32> {
+    let repeat = |x: f32, c: f32| {
+        if c <= 0.0 {
+            x
+        } else {
+            x - c * (x / c).round()
+        }
+    };
+
+    Vector3::new(
+        repeat(p.x, period.x),
+        repeat(p.y, period.y),
+        repeat(p.z, period.z),
+    )
+}
+
+/// Twists space around the y axis by `k` radians per unit of height.
+/// Distorts the field, so shapes using it need a `step_scale` below 1.0.
+pub fn op_twist(p: Vector3<f32>, k: f32) -> Vector3<f32> {
+    let angle = k * p.y;
+    let (s, c) = angle.sin_cos();
+    Vector3::new(c * p.x - s * p.z, p.y, s * p.x + c * p.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sdf_sphere_matches_analytic_intersection() {
+        let shape = SdfShape::new(
+            Vector3::new(4.0, 0.0, 0.0),
+            1.5,
+            |p| sd_sphere(p, 1.0),
+            Material::new(Vector3::new(255.0, 255.0, 255.0), 0.0, 0.0, 1.0),
+        );
+
+        let r = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+
+        match shape.intersect(&r, 0.001, f32::MAX) {
+            Some(res) => {
+                assert!((res.intersection_point - 3.0).abs() < 1e-3);
+                assert!((res.normal - Vector3::new(-1.0, 0.0, 0.0)).norm() < 1e-2);
+            }
+            None => panic!("expected an intersection"),
+        }
+    }
+
+    #[test]
+    fn test_sdf_miss_outside_bounds() {
+        let shape = SdfShape::new(
+            Vector3::new(4.0, 0.0, 0.0),
+            1.5,
+            |p| sd_box(p, Vector3::new(1.0, 1.0, 1.0)),
+            Material::new(Vector3::new(255.0, 255.0, 255.0), 0.0, 0.0, 1.0),
+        );
+
+        let r = Ray::new(Vector3::new(0.0, 0.0, 3.0), Vector3::new(1.0, 0.0, 0.0));
+
+        assert!(shape.intersect(&r, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn test_smooth_union_is_below_union() {
+        let d = op_smooth_union(0.3, 0.35, 0.2);
+        assert!(d < op_union(0.3, 0.35));
+    }
+
+    #[test]
+    fn test_mandelbulb_is_finite_at_the_center() {
+        assert_eq!(sd_mandelbulb(Vector3::zeros(), 8.0, 8), 0.0);
+        assert!(sd_mandelbulb(Vector3::new(0.0, 0.0, 1e-3), 8.0, 8).is_finite());
+        assert!(sd_mandelbulb(Vector3::new(3.0, 0.0, 0.0), 8.0, 8) > 0.0);
+    }
+}
@@ -1,16 +1,15 @@
-use crate::intersections::{Intersectable, IntersectionRecord};
+use crate::intersections::{tangent_frame, Intersectable, IntersectionRecord};
+use crate::materials::Material;
 use crate::ray::Ray;
-use nalgebra::Vector3;
-use sdl2::pixels::Color;
+use nalgebra::{Vector2, Vector3};
+use rand::{Rng, RngCore};
+use std::f32::consts::PI;
 
 #[derive(Debug, Clone)]
 pub struct Sphere {
     pub center: Vector3<f32>,
     pub radius: f32,
-    pub color: Vector3<f32>,
-    pub specular: f32,
-    pub reflective: f32,
-    pub refractive: f32,
+    pub material: Material,
     //ambient: Vector3<f32>,
     //diffuse: Vector3<f32>,
     //specular: Vector3<f32>
@@ -25,13 +24,18 @@ impl Sphere {
         reflective: f32,
         refractive: f32,
     ) -> Self {
+        Sphere::with_material(
+            center,
+            radius,
+            Material::new(color, specular, reflective, refractive),
+        )
+    }
+
+    pub fn with_material(center: Vector3<f32>, radius: f32, material: Material) -> Self {
         Sphere {
             center,
             radius,
-            color,
-            specular,
-            reflective,
-            refractive,
+            material,
         }
     }
 
@@ -44,27 +48,50 @@ impl Sphere {
     }
 
     pub fn specular(&self) -> f32 {
-        self.specular
+        self.material.specular
     }
 
     pub fn reflective(&self) -> f32 {
-        self.reflective
+        self.material.reflective
     }
 
     pub fn refractive(&self) -> f32 {
-        self.refractive
+        self.material.refractive
     }
 
-    pub fn color_vector(&self) -> Vector3<f32> {
-        self.color
+    fn record(&self, ray: &Ray, t: f32) -> IntersectionRecord {
+        let p = ray.point_at_parameter(t);
+        let local = p - self.center;
+        let normal = local / self.radius;
+        let uv = sphere_uv(normal);
+        // dp/du runs along the parallels
+        let (tangent, bitangent) = tangent_frame(normal, Vector3::new(-normal.z, 0.0, normal.x));
+
+        IntersectionRecord {
+            intersection_point: t,
+            intersection_vector: p,
+            object_center: self.center(),
+            object_index: 0,
+            normal: self
+                .material
+                .shading_normal(uv, local, normal, tangent, bitangent),
+            geometric_normal: normal,
+            object_color: self.material.color_at(uv, local),
+            object_specular: self.specular(),
+            object_reflective: self.reflective(),
+            object_refractive: self.refractive(),
+            object_model: self.material.model,
+            object_emission: self.material.emission,
+            object_coating: self.material.coating,
+        }
     }
+}
 
-    pub fn get_color(&self) -> Color {
-        let r = self.color[0];
-        let g = self.color[1];
-        let b = self.color[2];
-        Color::RGB(r as u8, g as u8, b as u8)
-    }
+/// Latitude/longitude parametrization of a point on the unit sphere, v = 1 at the north (+y) pole.
+pub fn sphere_uv(n: Vector3<f32>) -> Vector2<f32> {
+    let u = 0.5 + f32::atan2(n.z, n.x) / (2.0 * PI);
+    let v = 1.0 - f32::acos(n.y.clamp(-1.0, 1.0)) / PI;
+    Vector2::new(u, v)
 }
 
 impl Intersectable for Sphere {
@@ -72,6 +99,28 @@ impl Intersectable for Sphere {
         self.center
     }
 
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn area(&self) -> f32 {
+        4.0 * PI * self.radius.powi(2)
+    }
+
+    fn sample_surface(&self, rng: &mut dyn RngCore) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        // uniform direction: z uniform in [-1, 1] and uniform azimuth
+        let z = 1.0 - 2.0 * rng.gen::<f32>();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f32>();
+        let normal = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+
+        Some((self.center + normal * self.radius, normal))
+    }
+
     fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<IntersectionRecord> {
         // a = L - E ( Direction vector of ray, from start to end )
         let ray_to_sphere: Vector3<f32> = ray.origin() - self.center; // f = E - C ( Vector from center sphere to ray start )
@@ -85,36 +134,18 @@ impl Intersectable for Sphere {
         // println!("{}x^2 + {}x + {}", a, b, c);
         // println!("delta: {}", delta);
 
-        if delta > 0.0 {
+        if delta >= 0.0 {
             let delta_squared = f32::sqrt(delta);
             let t = (-b + -delta_squared) / a;
 
             if t_min < t && t < t_max {
-                let p = ray.point_at_parameter(t);
-                return Some(IntersectionRecord {
-                    intersection_point: t,
-                    intersection_vector: p,
-                    object_center: self.center(),
-                    object_color: self.get_color(),
-                    object_specular: self.specular(),
-                    object_reflective: self.reflective(),
-                    object_refractive: self.refractive(),
-                });
+                return Some(self.record(ray, t));
             }
 
             let t = (-b + delta_squared) / a;
 
             if t_min < t && t < t_max {
-                let p = ray.point_at_parameter(t);
-                return Some(IntersectionRecord {
-                    intersection_point: t,
-                    intersection_vector: p,
-                    object_center: self.center(),
-                    object_color: self.get_color(),
-                    object_specular: self.specular(),
-                    object_reflective: self.reflective(),
-                    object_refractive: self.refractive(),
-                });
+                return Some(self.record(ray, t));
             }
         }
         None
@@ -0,0 +1,198 @@
+use crate::intersections::IntersectionRecord;
+use crate::materials::ShadingModel;
+use nalgebra::{Matrix3, Vector3};
+use rand::Rng;
+use std::sync::OnceLock;
+
+pub const MIN_WAVELENGTH: f32 = 380.0;
+pub const MAX_WAVELENGTH: f32 = 720.0;
+// sodium d-line, where single-valued indices of refraction are usually quoted
+const NOMINAL_WAVELENGTH: f32 = 587.6;
+
+/// Index of refraction as a function of wavelength.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ior {
+    Constant(f32),
+    /// n = a + b / lambda^2, lambda in micrometers.
+    Cauchy {
+        a: f32,
+        b: f32,
+    },
+    /// n^2 = 1 + sum(b_i * lambda^2 / (lambda^2 - c_i)), lambda in micrometers.
+    Sellmeier {
+        b: [f32; 3],
+        c: [f32; 3],
+    },
+}
+
+impl Ior {
+    pub fn diamond() -> Self {
+        Ior::Cauchy {
+            a: 2.3818,
+            b: 0.0121,
+        }
+    }
+
+    /// Schott BK7 crown glass.
+    pub fn crown_glass() -> Self {
+        Ior::Sellmeier {
+            b: [1.039_612, 0.231_792_34, 1.010_469_5],
+            c: [0.006_000_699, 0.020_017_914, 103.560_65],
+        }
+    }
+
+    /// Flint glass, strongly dispersive, good for prisms.
+    pub fn flint_glass() -> Self {
+        Ior::Cauchy {
+            a: 1.7280,
+            b: 0.01342,
+        }
+    }
+
+    pub fn at(&self, wavelength: f32) -> f32 {
+        let micrometers = wavelength / 1000.0;
+        let l2 = micrometers * micrometers;
+
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                let sum: f32 = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    /// Value used when rendering in RGB.
+    pub fn nominal(&self) -> f32 {
+        self.at(NOMINAL_WAVELENGTH)
+    }
+}
+
+impl From<f32> for Ior {
+    fn from(n: f32) -> Self {
+        Ior::Constant(n)
+    }
+}
+
+pub fn sample_wavelength(rng: &mut impl Rng) -> f32 {
+    rng.gen_range(MIN_WAVELENGTH..MAX_WAVELENGTH)
+}
+
+/// Weights turning an RGB triple into its value at `wavelength`.
+///
+/// Smooth blue/green/red bands which sum to one, so white and grays stay flat spectra.
+/// Not an exact inverse of the color matching, but good enough for reflectances.
+pub fn rgb_basis(wavelength: f32) -> Vector3<f32> {
+    let blue = 1.0 - smoothstep(480.0, 510.0, wavelength);
+    let red = smoothstep(570.0, 600.0, wavelength);
+    Vector3::new(red, 1.0 - red - blue, blue)
+}
+
+/// Replaces the colors of a hit by their (gray) value at `wavelength` and dispersive indices of
+/// refraction by the index at that wavelength, so the regular BSDF code renders one wavelength.
+pub fn to_monochromatic(res: &mut IntersectionRecord, wavelength: f32) {
+    let basis = rgb_basis(wavelength);
+    res.object_coating.wavelength = Some(wavelength);
+    res.object_color = Vector3::repeat(res.object_color.dot(&basis));
+
+    if let ShadingModel::Dielectric {
+        ior,
+        roughness,
+        absorption,
+    } = res.object_model
+    {
+        res.object_model = ShadingModel::Dielectric {
+            ior: Ior::Constant(ior.at(wavelength)),
+            roughness,
+            absorption: Vector3::repeat(absorption.dot(&basis)),
+        };
+    }
+
+    if let ShadingModel::Subsurface { mean_free_path } = res.object_model {
+        res.object_model = ShadingModel::Subsurface {
+            mean_free_path: Vector3::repeat(mean_free_path.dot(&basis)),
+        };
+    }
+}
+
+/// Linear RGB estimate of a radiance sample carried at `wavelength`, where `radiance` was
+/// computed with monochromatic surfaces (lights and sky may still be colored).
+pub fn wavelength_to_rgb(radiance: Vector3<f32>, wavelength: f32) -> Vector3<f32> {
+    let value = radiance.dot(&rgb_basis(wavelength));
+    let rgb = xyz_to_linear_srgb() * cie_xyz(wavelength);
+
+    // normalized so a flat spectrum of 1.0 averages to white (1, 1, 1)
+    value * rgb.component_div(white_point()) * (MAX_WAVELENGTH - MIN_WAVELENGTH)
+}
+
+/// Analytic fit of the CIE 1931 2° color matching functions (Wyman, Sloan, Shirley 2013).
+pub fn cie_xyz(wavelength: f32) -> Vector3<f32> {
+    let g = |mu: f32, sigma_low: f32, sigma_high: f32| {
+        let sigma = if wavelength < mu {
+            sigma_low
+        } else {
+            sigma_high
+        };
+        let t = (wavelength - mu) / sigma;
+        f32::exp(-0.5 * t * t)
+    };
+
+    Vector3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+fn xyz_to_linear_srgb() -> Matrix3<f32> {
+    Matrix3::new(
+        3.2406, -1.5372, -0.4986, -0.9689, 1.8758, 0.0415, 0.0557, -0.2040, 1.0570,
+    )
+}
+
+/// RGB of a flat unit spectrum integrated over the sampled range.
+fn white_point() -> &'static Vector3<f32> {
+    static WHITE_POINT: OnceLock<Vector3<f32>> = OnceLock::new();
+
+    WHITE_POINT.get_or_init(|| {
+        let steps = 1000;
+        let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as f32;
+        (0..steps)
+            .map(|i| MIN_WAVELENGTH + (i as f32 + 0.5) * step)
+            .map(|wavelength| xyz_to_linear_srgb() * cie_xyz(wavelength) * step)
+            .sum()
+    })
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispersion_blue_bends_more() {
+        for ior in [Ior::diamond(), Ior::crown_glass(), Ior::flint_glass()] {
+            assert!(ior.at(450.0) > ior.at(650.0));
+        }
+        assert!((Ior::diamond().nominal() - 2.417).abs() < 1e-2);
+        assert!((Ior::crown_glass().nominal() - 1.5168).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_flat_spectrum_averages_to_white() {
+        let steps = 2000;
+        let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as f32;
+        let average: Vector3<f32> = (0..steps)
+            .map(|i| MIN_WAVELENGTH + (i as f32 + 0.5) * step)
+            .map(|wavelength| wavelength_to_rgb(Vector3::new(1.0, 1.0, 1.0), wavelength))
+            .sum::<Vector3<f32>>()
+            / steps as f32;
+
+        assert!((average - Vector3::new(1.0, 1.0, 1.0)).norm() < 1e-2);
+    }
+}
@@ -0,0 +1,315 @@
+use std::fmt::Write;
+use std::ops::Sub;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Counters of one thread. Only that thread adds to them, other threads just read them for the
+/// totals, so a plain load and store is enough and the tracing never waits on a shared counter.
+#[derive(Default)]
+struct Counters {
+    primary: AtomicU64,
+    shadow: AtomicU64,
+    secondary: AtomicU64,
+    intersection_tests: AtomicU64,
+    paths: AtomicU64,
+    path_segments: AtomicU64,
+}
+
+impl Counters {
+    fn add(counter: &AtomicU64, amount: u64) {
+        counter.store(counter.load(Ordering::Relaxed) + amount, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> RayCounts {
+        RayCounts {
+            primary: self.primary.load(Ordering::Relaxed),
+            shadow: self.shadow.load(Ordering::Relaxed),
+            secondary: self.secondary.load(Ordering::Relaxed),
+            intersection_tests: self.intersection_tests.load(Ordering::Relaxed),
+            paths: self.paths.load(Ordering::Relaxed),
+            path_segments: self.path_segments.load(Ordering::Relaxed),
+        }
+    }
+
+    fn restore(&self, counts: RayCounts) {
+        self.primary.store(counts.primary, Ordering::Relaxed);
+        self.shadow.store(counts.shadow, Ordering::Relaxed);
+        self.secondary.store(counts.secondary, Ordering::Relaxed);
+        self.intersection_tests
+            .store(counts.intersection_tests, Ordering::Relaxed);
+        self.paths.store(counts.paths, Ordering::Relaxed);
+        self.path_segments
+            .store(counts.path_segments, Ordering::Relaxed);
+    }
+}
+
+// the counters of every thread that traced a ray, kept after the thread ends
+static THREADS: Mutex<Vec<Arc<Counters>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static COUNTERS: Arc<Counters> = {
+        let counters = Arc::new(Counters::default());
+        THREADS.lock().unwrap().push(Arc::clone(&counters));
+        counters
+    };
+}
+
+// total time spent in each phase, in nanoseconds
+static PHASE_TIMES: [AtomicU64; Phase::ALL.len()] = [const { AtomicU64::new(0) }; Phase::ALL.len()];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RayKind {
+    /// Leaves the camera.
+    Primary,
+    /// Only checks whether a light is visible.
+    Shadow,
+    /// Reflected, refracted or bounced off a surface or medium.
+    Secondary,
+}
+
+pub fn count_ray(kind: RayKind) {
+    COUNTERS.with(|counters| {
+        let counter = match kind {
+            RayKind::Primary => &counters.primary,
+            RayKind::Shadow => &counters.shadow,
+            RayKind::Secondary => &counters.secondary,
+        };
+        Counters::add(counter, 1);
+    });
+}
+
+/// Ray-object tests, there is no acceleration structure so every ray tests the objects in turn.
+pub fn count_intersection_tests(tests: usize) {
+    COUNTERS.with(|counters| Counters::add(&counters.intersection_tests, tests as u64));
+}
+
+/// A finished camera path made of `segments` rays, the camera ray and what it spawned.
+pub fn count_path(segments: u64) {
+    COUNTERS.with(|counters| {
+        Counters::add(&counters.paths, 1);
+        Counters::add(&counters.path_segments, segments);
+    });
+}
+
+/// Totals since the program started.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RayCounts {
+    pub primary: u64,
+    pub shadow: u64,
+    pub secondary: u64,
+    pub intersection_tests: u64,
+    pub paths: u64,
+    pub path_segments: u64,
+}
+
+impl RayCounts {
+    pub fn rays(&self) -> u64 {
+        self.primary + self.shadow + self.secondary
+    }
+
+    /// Mean number of rays per camera path, 0 before any path was traced.
+    pub fn average_path_depth(&self) -> f64 {
+        if self.paths == 0 {
+            return 0.0;
+        }
+        self.path_segments as f64 / self.paths as f64
+    }
+}
+
+impl Sub for RayCounts {
+    type Output = RayCounts;
+
+    fn sub(self, earlier: RayCounts) -> RayCounts {
+        RayCounts {
+            primary: self.primary.saturating_sub(earlier.primary),
+            shadow: self.shadow.saturating_sub(earlier.shadow),
+            secondary: self.secondary.saturating_sub(earlier.secondary),
+            intersection_tests: self
+                .intersection_tests
+                .saturating_sub(earlier.intersection_tests),
+            paths: self.paths.saturating_sub(earlier.paths),
+            path_segments: self.path_segments.saturating_sub(earlier.path_segments),
+        }
+    }
+}
+
+/// Runs `f` without counting the rays it traces, for extra rays that only fill auxiliary
+/// passes and would otherwise inflate the counts of the render.
+pub fn uncounted<T>(f: impl FnOnce() -> T) -> T {
+    let before = thread_ray_counts();
+    let result = f();
+    COUNTERS.with(|counters| counters.restore(before));
+    result
+}
+
+/// Totals of all threads.
+pub fn ray_counts() -> RayCounts {
+    THREADS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|counters| counters.snapshot())
+        .fold(RayCounts::default(), |total, counts| RayCounts {
+            primary: total.primary + counts.primary,
+            shadow: total.shadow + counts.shadow,
+            secondary: total.secondary + counts.secondary,
+            intersection_tests: total.intersection_tests + counts.intersection_tests,
+            paths: total.paths + counts.paths,
+            path_segments: total.path_segments + counts.path_segments,
+        })
+}
+
+/// Counts of the calling thread alone, e.g. to see what one sample traced.
+pub fn thread_ray_counts() -> RayCounts {
+    COUNTERS.with(|counters| counters.snapshot())
+}
+
+/// Step of a render to the file, timed separately.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    SceneLoad,
+    Render,
+    Denoise,
+    Save,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [Phase::SceneLoad, Phase::Render, Phase::Denoise, Phase::Save];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::SceneLoad => "scene_load",
+            Phase::Render => "render",
+            Phase::Denoise => "denoise",
+            Phase::Save => "save",
+        }
+    }
+}
+
+/// Runs `f`, adding the time it takes to `phase`.
+pub fn time_phase<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    PHASE_TIMES[phase as usize].fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    result
+}
+
+pub fn phase_time(phase: Phase) -> Duration {
+    Duration::from_nanos(PHASE_TIMES[phase as usize].load(Ordering::Relaxed))
+}
+
+/// Ray counts and phase times of a render, printed or written as JSON when it's done.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderStats {
+    pub counts: RayCounts,
+    pub phases: Vec<(Phase, Duration)>,
+}
+
+impl RenderStats {
+    /// Everything counted so far.
+    pub fn collect() -> Self {
+        RenderStats {
+            counts: ray_counts(),
+            phases: Phase::ALL
+                .iter()
+                .map(|&phase| (phase, phase_time(phase)))
+                .collect(),
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let counts = &self.counts;
+        let mut summary = format!(
+            "rays {}: primary {}, shadow {}, secondary {}\n\
+             intersection tests {}\n\
+             average path depth {:.2}\n",
+            counts.rays(),
+            counts.primary,
+            counts.shadow,
+            counts.secondary,
+            counts.intersection_tests,
+            counts.average_path_depth()
+        );
+        for (phase, time) in &self.phases {
+            let _ = writeln!(
+                summary,
+                "{} {:.1} ms",
+                phase.name(),
+                time.as_secs_f64() * 1000.0
+            );
+        }
+        summary
+    }
+
+    pub fn to_json(&self) -> String {
+        let counts = &self.counts;
+        let phases = self
+            .phases
+            .iter()
+            .map(|(phase, time)| format!("\"{}\": {:.6}", phase.name(), time.as_secs_f64()))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        format!(
+            "{{\n  \"rays\": {{\"primary\": {}, \"shadow\": {}, \"secondary\": {}, \"total\": {}}},\n  \
+             \"intersection_tests\": {},\n  \
+             \"paths\": {},\n  \
+             \"average_path_depth\": {:.4},\n  \
+             \"phase_seconds\": {{{}}}\n}}\n",
+            counts.primary,
+            counts.shadow,
+            counts.secondary,
+            counts.rays(),
+            counts.intersection_tests,
+            counts.paths,
+            counts.average_path_depth(),
+            phases
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn test_counts_are_summed_across_threads() {
+        let before = ray_counts();
+        let before_thread = thread_ray_counts();
+
+        (0..1000).into_par_iter().for_each(|_| {
+            count_ray(RayKind::Secondary);
+            count_intersection_tests(3);
+        });
+        count_path(4);
+
+        // other tests may trace at the same time, so only a lower bound holds for the totals
+        let difference = ray_counts() - before;
+        assert!(difference.secondary >= 1000);
+        assert!(difference.intersection_tests >= 3000);
+
+        let thread_difference = thread_ray_counts() - before_thread;
+        assert_eq!(thread_difference.paths, 1);
+        assert_eq!(thread_difference.average_path_depth(), 4.0);
+    }
+
+    #[test]
+    fn test_uncounted_rays_leave_the_counts_alone() {
+        let before = thread_ray_counts();
+
+        let traced = uncounted(|| {
+            count_ray(RayKind::Shadow);
+            count_intersection_tests(5);
+            true
+        });
+        count_ray(RayKind::Primary);
+
+        assert!(traced);
+        let difference = thread_ray_counts() - before;
+        assert_eq!(difference.rays(), 1);
+        assert_eq!(difference.primary, 1);
+        assert_eq!(difference.intersection_tests, 0);
+    }
+}
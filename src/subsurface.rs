@@ -0,0 +1,99 @@
+use crate::bsdf;
+use crate::intersections::IntersectionRecord;
+use crate::layers::Coating;
+use crate::materials::ShadingModel;
+use crate::media::{sample_henyey_greenstein, Medium};
+use crate::ray::Ray;
+use crate::scene::Scene;
+use nalgebra::Vector3;
+use rand::Rng;
+
+// walks longer than this are considered absorbed
+const MAX_WALK_STEPS: u32 = 256;
+const WALK_EPSILON: f32 = 1e-4;
+
+/// Single scattering albedo giving roughly `albedo` as the multiple scattering albedo of a
+/// semi-infinite slab (van de Hulst inversion, as used by Chiang et al. 2016).
+pub fn single_scattering_albedo(albedo: f32) -> f32 {
+    let a = albedo.clamp(0.0, 1.0);
+    let s = 4.09712 + 4.20863 * a - f32::sqrt(9.59217 + 41.6808 * a + 17.7126 * a * a);
+    (1.0 - s * s).clamp(0.0, 1.0)
+}
+
+/// Interior medium of a subsurface material with the given albedo (0-1) and mean free path.
+pub fn interior_medium(albedo: Vector3<f32>, mean_free_path: Vector3<f32>) -> Medium {
+    let extinction = mean_free_path.map(|d| 1.0 / d.max(1e-6));
+    let single_scattering = albedo.map(single_scattering_albedo);
+
+    Medium::new(
+        extinction.component_mul(&single_scattering.map(|a| 1.0 - a)),
+        extinction.component_mul(&single_scattering),
+        0.0,
+    )
+}
+
+/// Follows light that refracted into the object of `res` along `direction` until it leaves
+/// again. Returns the exit as a white diffuse surface, where the path continues, together with
+/// the throughput of the walk, or None if the light got absorbed.
+pub fn random_walk(
+    scene: &Scene,
+    res: &IntersectionRecord,
+    direction: Vector3<f32>,
+    mean_free_path: Vector3<f32>,
+    rng: &mut impl Rng,
+) -> Option<(IntersectionRecord, Vector3<f32>)> {
+    let object = &scene.objects[res.object_index];
+    let medium = interior_medium(bsdf::albedo(res), mean_free_path);
+    let mut p = res.intersection_vector;
+    let mut direction = direction.normalize();
+    let mut weight = Vector3::new(1.0, 1.0, 1.0);
+
+    for _ in 0..MAX_WALK_STEPS {
+        let ray = Ray::new(p, direction);
+        // direction is normalized, so the ray parameter is a distance
+        let boundary = object.intersect(&ray, WALK_EPSILON, f32::MAX)?;
+        let distance = medium.sample_distance(rng);
+
+        if distance >= boundary.intersection_point {
+            weight = weight.component_mul(&medium.pass_weight(boundary.intersection_point));
+            return Some((exit_record(boundary, res.object_index), weight));
+        }
+
+        weight = weight
+            .component_mul(&medium.scatter_weight(distance))
+            .component_mul(&medium.albedo());
+        p += direction * distance;
+        direction = sample_henyey_greenstein(direction, medium.g, rng);
+    }
+
+    None
+}
+
+/// Light leaves the surface diffusely, the color was already picked up inside.
+fn exit_record(mut exit: IntersectionRecord, object_index: usize) -> IntersectionRecord {
+    exit.object_index = object_index;
+    exit.normal = exit.geometric_normal;
+    exit.object_color = Vector3::repeat(255.0);
+    exit.object_specular = 0.0;
+    exit.object_reflective = 0.0;
+    exit.object_model = ShadingModel::Phong;
+    exit.object_emission = Vector3::zeros();
+    exit.object_coating = Coating::default();
+    exit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_scattering_albedo_limits() {
+        assert!(single_scattering_albedo(0.0).abs() < 1e-3);
+        assert!((single_scattering_albedo(1.0) - 1.0).abs() < 1e-3);
+
+        // multiple scattering darkens, so the single scattering albedo must be higher
+        for albedo in [0.2, 0.5, 0.8] {
+            assert!(single_scattering_albedo(albedo) > albedo);
+        }
+    }
+}
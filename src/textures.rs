@@ -0,0 +1,288 @@
+use crate::framebuffer::srgb_to_linear;
+use nalgebra::{Vector2, Vector3};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Color source evaluated at a hit point.
+///
+/// `uv` is the surface parametrization of the shape, `p` the hit point in object space.
+/// Colors use the same 0-255 range as the rest of the renderer.
+pub trait Texture: Debug + Send + Sync {
+    fn value(&self, uv: Vector2<f32>, p: Vector3<f32>) -> Vector3<f32>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SolidColor {
+    pub color: Vector3<f32>,
+}
+
+impl SolidColor {
+    pub fn new(color: Vector3<f32>) -> Self {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _uv: Vector2<f32>, _p: Vector3<f32>) -> Vector3<f32> {
+        self.color
+    }
+}
+
+/// Alternates two textures in a grid of `frequency` x `frequency` squares over the uv range.
+#[derive(Debug, Clone)]
+pub struct CheckerTexture {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub frequency: f32,
+}
+
+impl CheckerTexture {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, frequency: f32) -> Self {
+        CheckerTexture {
+            even,
+            odd,
+            frequency,
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, uv: Vector2<f32>, p: Vector3<f32>) -> Vector3<f32> {
+        let u = (uv.x * self.frequency).floor() as i64;
+        let v = (uv.y * self.frequency).floor() as i64;
+
+        if (u + v) % 2 == 0 {
+            self.even.value(uv, p)
+        } else {
+            self.odd.value(uv, p)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum GradientDirection {
+    U,
+    V,
+}
+
+/// Linear blend from `from` to `to` along one of the uv axes.
+#[derive(Debug, Clone)]
+pub struct GradientTexture {
+    pub from: Vector3<f32>,
+    pub to: Vector3<f32>,
+    pub direction: GradientDirection,
+}
+
+impl GradientTexture {
+    pub fn new(from: Vector3<f32>, to: Vector3<f32>, direction: GradientDirection) -> Self {
+        GradientTexture {
+            from,
+            to,
+            direction,
+        }
+    }
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, uv: Vector2<f32>, _p: Vector3<f32>) -> Vector3<f32> {
+        let t = match self.direction {
+            GradientDirection::U => uv.x,
+            GradientDirection::V => uv.y,
+        };
+
+        self.from.lerp(&self.to, t.clamp(0.0, 1.0))
+    }
+}
+
+/// How texel coordinates outside of the image are resolved.
+#[derive(Debug, Clone, Copy)]
+pub enum WrapMode {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+impl WrapMode {
+    fn apply(&self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::MirroredRepeat => {
+                let period = i.rem_euclid(2 * size);
+                if period < size {
+                    period
+                } else {
+                    2 * size - 1 - period
+                }
+            }
+            WrapMode::ClampToEdge => i.clamp(0, size - 1),
+        };
+
+        wrapped as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FilterMode {
+    Nearest,
+    Bilinear,
+}
+
+/// Texture backed by an image file, v = 0 is the bottom row of the image.
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    pub wrap: WrapMode,
+    pub filter: FilterMode,
+    width: usize,
+    height: usize,
+    texels: Vec<Vector3<f32>>,
+}
+
+impl ImageTexture {
+    pub fn new(
+        width: usize,
+        height: usize,
+        texels: Vec<Vector3<f32>>,
+        wrap: WrapMode,
+        filter: FilterMode,
+    ) -> Self {
+        assert_eq!(texels.len(), width * height, "texel count must match size");
+
+        ImageTexture {
+            wrap,
+            filter,
+            width,
+            height,
+            texels,
+        }
+    }
+
+    /// Loads a PNG or JPEG file. Color images are sRGB encoded and decoded with `srgb` to the
+    /// renderer's linear 0-255, data like normal or height maps is used as stored.
+    pub fn from_file(
+        path: &str,
+        wrap: WrapMode,
+        filter: FilterMode,
+        srgb: bool,
+    ) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("{}: {}", path, e))?
+            .into_rgb8();
+        let (width, height) = image.dimensions();
+
+        let texels = image
+            .pixels()
+            .map(|pixel| {
+                let texel = Vector3::new(pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+                if srgb {
+                    texel.map(|c| srgb_to_linear(c / 255.0) * 255.0)
+                } else {
+                    texel
+                }
+            })
+            .collect();
+
+        Ok(ImageTexture::new(
+            width as usize,
+            height as usize,
+            texels,
+            wrap,
+            filter,
+        ))
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Vector3<f32> {
+        let x = self.wrap.apply(x, self.width);
+        let y = self.wrap.apply(y, self.height);
+        self.texels[y * self.width + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, uv: Vector2<f32>, _p: Vector3<f32>) -> Vector3<f32> {
+        // continuous texel coordinates, texel centers sit at half-integers
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f32 - 0.5;
+
+        match self.filter {
+            FilterMode::Nearest => self.texel(x.round() as i64, y.round() as i64),
+            FilterMode::Bilinear => {
+                let x0 = x.floor();
+                let y0 = y.floor();
+                let tx = x - x0;
+                let ty = y - y0;
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = self.texel(x0, y0).lerp(&self.texel(x0 + 1, y0), tx);
+                let bottom = self.texel(x0, y0 + 1).lerp(&self.texel(x0 + 1, y0 + 1), tx);
+                top.lerp(&bottom, ty)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_by_one(wrap: WrapMode) -> ImageTexture {
+        ImageTexture::new(
+            2,
+            1,
+            vec![
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(200.0, 100.0, 50.0),
+            ],
+            wrap,
+            FilterMode::Bilinear,
+        )
+    }
+
+    #[test]
+    fn test_bilinear_halfway_between_texels() {
+        let texture = two_by_one(WrapMode::ClampToEdge);
+        let color = texture.value(Vector2::new(0.5, 0.5), Vector3::zeros());
+        assert!((color - Vector3::new(100.0, 50.0, 25.0)).norm() < 1e-3);
+    }
+
+    #[test]
+    fn test_color_images_are_decoded_to_linear() {
+        let path = std::env::temp_dir().join("textures_test_gray.png");
+        image::RgbImage::from_pixel(1, 1, image::Rgb([128, 128, 128]))
+            .save(&path)
+            .unwrap();
+        let path = path.to_str().unwrap();
+        let load = |srgb| {
+            ImageTexture::from_file(path, WrapMode::Repeat, FilterMode::Nearest, srgb)
+                .unwrap()
+                .value(Vector2::new(0.5, 0.5), Vector3::zeros())
+        };
+
+        // sRGB 128 is about 22% of the linear intensity of white
+        assert!((load(true).x - 55.0).abs() < 0.5);
+        assert_eq!(load(false), Vector3::repeat(128.0));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_wrap_modes() {
+        assert_eq!(WrapMode::Repeat.apply(-1, 4), 3);
+        assert_eq!(WrapMode::MirroredRepeat.apply(-1, 4), 0);
+        assert_eq!(WrapMode::MirroredRepeat.apply(5, 4), 2);
+        assert_eq!(WrapMode::ClampToEdge.apply(9, 4), 3);
+    }
+
+    #[test]
+    fn test_checker_alternates() {
+        let checker = CheckerTexture::new(
+            Arc::new(SolidColor::new(Vector3::new(255.0, 255.0, 255.0))),
+            Arc::new(SolidColor::new(Vector3::zeros())),
+            4.0,
+        );
+
+        let a = checker.value(Vector2::new(0.1, 0.1), Vector3::zeros());
+        let b = checker.value(Vector2::new(0.3, 0.1), Vector3::zeros());
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::framebuffer::Framebuffer;
+use nalgebra::Vector3;
+
+// luminance that maps to white in the extended Reinhard operator, before exposure
+pub const DEFAULT_WHITE_POINT: f32 = 4.0;
+// linear white of the Hable curve
+const HABLE_WHITE: f32 = 11.2;
+
+/// Curve compressing the linear HDR image into the displayable 0-1 range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapping {
+    /// Everything above 1.0 is cut off.
+    Clamp,
+    Reinhard,
+    /// Reinhard which reaches white at a finite luminance instead of never.
+    ReinhardExtended,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// John Hable's Uncharted 2 filmic curve.
+    Hable,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 5] = [
+        ToneMapping::Clamp,
+        ToneMapping::Reinhard,
+        ToneMapping::ReinhardExtended,
+        ToneMapping::Aces,
+        ToneMapping::Hable,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapping::Clamp => "clamp",
+            ToneMapping::Reinhard => "reinhard",
+            ToneMapping::ReinhardExtended => "reinhard-extended",
+            ToneMapping::Aces => "aces",
+            ToneMapping::Hable => "hable",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        ToneMapping::ALL
+            .iter()
+            .find(|operator| operator.name() == name)
+            .cloned()
+            .ok_or(format!("unknown tone mapping operator: {}", name))
+    }
+
+    /// The operator after this one, wrapping around.
+    pub fn next(&self) -> Self {
+        let index = ToneMapping::ALL
+            .iter()
+            .position(|operator| operator == self)
+            .unwrap();
+        ToneMapping::ALL[(index + 1) % ToneMapping::ALL.len()]
+    }
+}
+
+/// Output stage between the renderer and the display or an 8/16-bit file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapper {
+    pub operator: ToneMapping,
+    /// Exposure in stops, every +1 doubles the brightness.
+    pub exposure: f32,
+    pub white_point: f32,
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        ToneMapper {
+            operator: ToneMapping::Clamp,
+            exposure: 0.0,
+            white_point: DEFAULT_WHITE_POINT,
+        }
+    }
+}
+
+impl ToneMapper {
+    pub fn new(operator: ToneMapping, exposure: f32) -> Self {
+        ToneMapper {
+            operator,
+            exposure,
+            ..ToneMapper::default()
+        }
+    }
+
+    /// Maps one linear color to linear 0-1, still to be sRGB encoded.
+    pub fn map(&self, color: Vector3<f32>) -> Vector3<f32> {
+        let color = color.map(|c| c.max(0.0)) * 2f32.powf(self.exposure);
+
+        let mapped = match self.operator {
+            ToneMapping::Clamp => color,
+            // both Reinhard variants work on luminance so that hues don't shift
+            ToneMapping::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMapping::ReinhardExtended => {
+                let white = self.white_point.max(1e-3);
+                scale_luminance(color, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMapping::Aces => color.map(aces),
+            ToneMapping::Hable => color.map(|c| hable(2.0 * c) / hable(HABLE_WHITE)),
+        };
+
+        mapped.map(|c| c.clamp(0.0, 1.0))
+    }
+
+    pub fn apply(&self, framebuffer: &Framebuffer) -> Framebuffer {
+        Framebuffer {
+            width: framebuffer.width,
+            height: framebuffer.height,
+            pixels: framebuffer.pixels.iter().map(|&p| self.map(p)).collect(),
+        }
+    }
+}
+
+pub fn luminance(color: Vector3<f32>) -> f32 {
+    color.dot(&Vector3::new(0.2126, 0.7152, 0.0722))
+}
+
+fn scale_luminance(color: Vector3<f32>, curve: impl Fn(f32) -> f32) -> Vector3<f32> {
+    let l = luminance(color);
+    if l <= 0.0 {
+        return Vector3::zeros();
+    }
+    color * (curve(l) / l)
+}
+
+fn aces(x: f32) -> f32 {
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    (x * (a * x + b)) / (x * (c * x + d) + e)
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operators_are_monotonic_and_bounded() {
+        for operator in ToneMapping::ALL {
+            let tone_mapper = ToneMapper::new(operator, 0.0);
+            let mut previous = 0.0;
+
+            for i in 0..200 {
+                let c = tone_mapper.map(Vector3::repeat(i as f32 * 0.1)).x;
+                assert!(c >= previous - 1e-6, "{} is not monotonic", operator.name());
+                assert!(c <= 1.0);
+                previous = c;
+            }
+            assert!(tone_mapper.map(Vector3::zeros()).x.abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_exposure_and_white_point() {
+        let color = Vector3::new(0.1, 0.2, 0.3);
+        let brighter = ToneMapper::new(ToneMapping::Clamp, 1.0).map(color);
+        assert!((brighter - 2.0 * color).norm() < 1e-6);
+
+        let white = Vector3::repeat(DEFAULT_WHITE_POINT);
+        let mapped = ToneMapper::new(ToneMapping::ReinhardExtended, 0.0).map(white);
+        assert!((mapped - Vector3::repeat(1.0)).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::intersections::{Intersectable, IntersectionRecord};
+use crate::materials::{Material, ShadingModel};
+use crate::media::{boundary_interval, scatter_record, Medium};
+use crate::noise::lerp;
+use crate::ray::Ray;
+use nalgebra::Vector3;
+use rand::Rng;
+use std::fs;
+
+// first token of a density grid file
+const GRID_MAGIC: &str = "DENSITY";
+
+/// Dense 3D grid of densities, x varies fastest, then y, then z.
+///
+/// Stored on disk as a text header `DENSITY <nx> <ny> <nz>` ended by a newline, followed by
+/// nx * ny * nz little-endian f32 values in the same order.
+#[derive(Debug, Clone)]
+pub struct DensityGrid {
+    pub resolution: [usize; 3],
+    pub data: Vec<f32>,
+    max_density: f32,
+}
+
+impl DensityGrid {
+    pub fn new(resolution: [usize; 3], data: Vec<f32>) -> Result<Self, String> {
+        let count = resolution.iter().product::<usize>();
+        if count == 0 || data.len() != count {
+            return Err(format!(
+                "density grid {:?} needs {} values, got {}",
+                resolution,
+                count,
+                data.len()
+            ));
+        }
+
+        let max_density = data.iter().cloned().fold(0.0, f32::max);
+
+        Ok(DensityGrid {
+            resolution,
+            data,
+            max_density,
+        })
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let header_end = bytes
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or("density grid header is missing")?;
+        let header = std::str::from_utf8(&bytes[..header_end]).map_err(|e| e.to_string())?;
+
+        let mut tokens = header.split_whitespace();
+        if tokens.next() != Some(GRID_MAGIC) {
+            return Err(format!("{} is not a density grid", path));
+        }
+
+        let mut resolution = [0; 3];
+        for axis in resolution.iter_mut() {
+            *axis = tokens
+                .next()
+                .ok_or("density grid resolution is incomplete")?
+                .parse()
+                .map_err(|e: std::num::ParseIntError| e.to_string())?;
+        }
+
+        let data = bytes[header_end + 1..]
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+
+        DensityGrid::new(resolution, data)
+    }
+
+    pub fn max_density(&self) -> f32 {
+        self.max_density
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        let [nx, ny, _] = self.resolution;
+        self.data[(z * ny + y) * nx + x]
+    }
+
+    /// Trilinearly interpolated density at `uvw` in [0, 1]^3 grid space, zero outside the grid.
+    pub fn density(&self, uvw: Vector3<f32>) -> f32 {
+        if uvw.iter().any(|&c| !(0.0..=1.0).contains(&c)) {
+            return 0.0;
+        }
+
+        // voxel values sit at voxel centers, clamp to the border half a voxel from the edge
+        let mut base = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let x = (uvw[axis] * n as f32 - 0.5).clamp(0.0, (n - 1) as f32);
+            base[axis] = (x as usize).min(n.saturating_sub(2));
+            fraction[axis] = if n > 1 { x - base[axis] as f32 } else { 0.0 };
+        }
+
+        let next = |axis: usize| (base[axis] + 1).min(self.resolution[axis] - 1);
+        let [x0, y0, z0] = base;
+        let (x1, y1, z1) = (next(0), next(1), next(2));
+        let [fx, fy, fz] = fraction;
+
+        let front = lerp(
+            lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), fx),
+            lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), fx),
+            fy,
+        );
+        let back = lerp(
+            lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), fx),
+            lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), fx),
+            fy,
+        );
+        lerp(front, back, fz)
+    }
+}
+
+/// Cloud, smoke or fire with density varying over a voxel grid, rendered with delta tracking.
+///
+/// The grid spans the box from `origin` to `origin + size`, `medium` holds the coefficients at
+/// density 1. Rays are only tracked inside `boundary`, which should enclose the non-empty voxels
+/// as tightly as possible.
+pub struct GridMedium {
+    pub boundary: Box<dyn Intersectable>,
+    pub grid: DensityGrid,
+    pub origin: Vector3<f32>,
+    pub size: Vector3<f32>,
+    pub medium: Medium,
+    /// Light emitted at the densest voxels (0-255 scale), fades with the density.
+    pub emission: Vector3<f32>,
+    material: Material,
+}
+
+impl GridMedium {
+    pub fn new(
+        boundary: impl Intersectable + 'static,
+        grid: DensityGrid,
+        origin: Vector3<f32>,
+        size: Vector3<f32>,
+        medium: Medium,
+    ) -> Self {
+        let mut material = Material::new(medium.albedo() * 255.0, 0.0, 0.0, 1.0);
+        material.model = ShadingModel::Volume { g: medium.g };
+
+        GridMedium {
+            boundary: Box::new(boundary),
+            grid,
+            origin,
+            size,
+            medium,
+            emission: Vector3::zeros(),
+            material,
+        }
+    }
+
+    /// Makes the volume glow, for fire and explosions.
+    pub fn with_emission(mut self, color: Vector3<f32>, strength: f32) -> Self {
+        self.emission = color * strength;
+        self
+    }
+
+    fn density_at(&self, p: Vector3<f32>) -> f32 {
+        self.grid
+            .density((p - self.origin).component_div(&self.size))
+    }
+
+    /// Extinction bound over the whole grid, the rate of tentative collisions.
+    fn majorant(&self) -> f32 {
+        self.grid.max_density() * self.medium.mean_extinction()
+    }
+}
+
+impl Intersectable for GridMedium {
+    fn center(&self) -> Vector3<f32> {
+        self.boundary.center()
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<IntersectionRecord> {
+        let (t_enter, t_exit) = boundary_interval(self.boundary.as_ref(), ray, t_min, t_max)?;
+        let majorant = self.majorant() * ray.direction().norm();
+        if majorant <= 0.0 {
+            return None;
+        }
+
+        // delta tracking: tentative collisions at the majorant rate, accepted with the ratio of
+        // the real extinction, the rejected ones are null collisions
+        let mut rng = rand::thread_rng();
+        let mut t = t_enter;
+        loop {
+            t -= (1.0 - rng.gen::<f32>()).ln() / majorant;
+            if t >= t_exit {
+                return None;
+            }
+
+            let density = self.density_at(ray.point_at_parameter(t));
+            if rng.gen::<f32>() * self.grid.max_density() < density {
+                let mut res = scatter_record(ray, t, &self.medium, self.center());
+                // absorbing particles emit, scattering ones only redirect light
+                res.object_emission = self.emission
+                    * (density / self.grid.max_density())
+                    * (1.0 - self.medium.albedo().mean());
+                return Some(res);
+            }
+        }
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let (t_enter, t_exit) = match boundary_interval(self.boundary.as_ref(), ray, t_min, t_max) {
+            Some(interval) => interval,
+            None => return 1.0,
+        };
+        let majorant = self.majorant() * ray.direction().norm();
+        if majorant <= 0.0 {
+            return 1.0;
+        }
+
+        // ratio tracking: same tentative collisions, each scales the estimate by the null ratio
+        let mut rng = rand::thread_rng();
+        let mut transmittance = 1.0;
+        let mut t = t_enter;
+        loop {
+            t -= (1.0 - rng.gen::<f32>()).ln() / majorant;
+            if t >= t_exit {
+                return transmittance;
+            }
+
+            let density = self.density_at(ray.point_at_parameter(t));
+            transmittance *= 1.0 - density / self.grid.max_density();
+            if transmittance <= 1e-4 {
+                return 0.0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_density_interpolates_between_voxels() {
+        let grid = DensityGrid::new([2, 1, 1], vec![0.0, 1.0]).unwrap();
+
+        assert_eq!(grid.density(Vector3::new(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.density(Vector3::new(0.75, 0.5, 0.5)), 1.0);
+        assert!((grid.density(Vector3::new(0.5, 0.5, 0.5)) - 0.5).abs() < 1e-6);
+        assert_eq!(grid.density(Vector3::new(1.5, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn test_grid_file_round_trip() {
+        let path = std::env::temp_dir().join("density_grid_round_trip.bin");
+        let values = [0.0f32, 0.5, 1.0, 2.0, 0.25, 0.75];
+
+        let mut bytes = b"DENSITY 3 2 1\n".to_vec();
+        for value in values.iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        fs::write(&path, bytes).unwrap();
+
+        let grid = DensityGrid::from_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(grid.resolution, [3, 2, 1]);
+        assert_eq!(grid.data, values.to_vec());
+        assert_eq!(grid.max_density(), 2.0);
+    }
+}